@@ -0,0 +1,31 @@
+//! Details shared by the error types of the transports
+
+use core::fmt;
+
+/// Step of the protocol which was running when a pin or bus failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Operation {
+    /// Sampling the parallel inputs into the chain.
+    Load,
+    /// Clocking bits through the chain.
+    Shift,
+    /// Copying the chain onto the outputs.
+    Latch,
+    /// Resetting the chain.
+    Clear,
+    /// Enabling or disabling the outputs.
+    OutputEnable,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Load => "loading",
+            Operation::Shift => "shifting",
+            Operation::Latch => "latching",
+            Operation::Clear => "clearing",
+            Operation::OutputEnable => "enabling or disabling outputs",
+        })
+    }
+}
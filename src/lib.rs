@@ -6,5 +6,13 @@
 #![no_std]
 
 extern crate embedded_hal as hal;
+#[cfg(feature = "std")]
+extern crate std;
 
+pub mod error;
+pub mod piso;
+#[cfg(feature = "sim")]
+pub mod sim;
 pub mod sipo;
+#[cfg(feature = "std")]
+pub mod vcd;
@@ -0,0 +1,369 @@
+//! Parallel-in serial-out shift register
+
+use core::cell::RefCell;
+use core::fmt;
+
+use hal::digital::{self, ErrorType};
+
+use crate::error::Operation;
+use crate::hal::digital::{InputPin, OutputPin};
+
+pub mod spi;
+
+type SRErr<Clock, Load, Data> = SRError<<Clock as ErrorType>::Error, <Load as ErrorType>::Error, <Data as ErrorType>::Error>;
+
+/// Input pin of the shift register
+pub struct ShiftRegisterPin<'a, T, const N: usize, const B: usize>
+where
+    T: Transport,
+{
+    shift_register: &'a ShiftRegister<T, N, B>,
+    index: usize,
+}
+
+impl<'a, T, const N: usize, const B: usize> ShiftRegisterPin<'a, T, N, B>
+where
+    T: Transport,
+{
+    fn new(shift_register: &'a ShiftRegister<T, N, B>, index: usize) -> Self {
+        ShiftRegisterPin {
+            shift_register,
+            index,
+        }
+    }
+
+    fn read(&self) -> Result<bool, T::Error> {
+        if self.shift_register.read_mode == ReadMode::Live {
+            self.shift_register.update()?;
+        }
+        Ok(self.shift_register.input_state.borrow()[self.index])
+    }
+}
+
+impl<T, const N: usize, const B: usize> ErrorType for ShiftRegisterPin<'_, T, N, B>
+where
+    T: Transport,
+{
+    type Error = T::Error;
+}
+
+impl<T, const N: usize, const B: usize> InputPin for ShiftRegisterPin<'_, T, N, B>
+where
+    T: Transport,
+{
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.read()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.read()?)
+    }
+}
+
+/// How the input pins obtain their level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Every read of an input pin loads and shifts in the whole chain
+    Live,
+    /// Input pins return the snapshot taken by the last [`ShiftRegister::read`]
+    Cached,
+}
+
+/// Level of the load pin which makes the register sample its parallel inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPolarity {
+    /// The inputs are sampled while the load pin is low (74HC165 `/PL`)
+    ActiveLow,
+    /// The inputs are sampled while the load pin is high (CD4021 `P/S`)
+    ActiveHigh,
+}
+
+impl LoadPolarity {
+    fn pulse<Load>(self, load: &mut Load) -> Result<(), Load::Error>
+    where
+        Load: OutputPin,
+    {
+        match self {
+            LoadPolarity::ActiveLow => {
+                load.set_low()?;
+                load.set_high()
+            }
+            LoadPolarity::ActiveHigh => {
+                load.set_high()?;
+                load.set_low()
+            }
+        }
+    }
+}
+
+/// Moves the state of the parallel inputs out of the shift register chain
+pub trait Transport {
+    /// Error returned when the transfer fails
+    type Error: digital::Error;
+
+    /// Load the parallel inputs into the chain, then shift them into `bytes`
+    ///
+    /// Bytes are filled first to last and most significant bit first, so the first byte holds
+    /// the register nearest to the data line with its input D7 in the most significant bit.
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// Transport which bit-bangs the clock and load output pins and samples the data input pin
+pub struct BitBang<Clock, Load, Data>
+where
+    Clock: OutputPin,
+    Load: OutputPin,
+    Data: InputPin,
+{
+    clock: Clock,
+    load: Load,
+    data: Data,
+    load_polarity: LoadPolarity,
+}
+
+impl<Clock, Load, Data> BitBang<Clock, Load, Data>
+where
+    Clock: OutputPin,
+    Load: OutputPin,
+    Data: InputPin,
+{
+    /// Creates a new bit-banged transport from clock and load output pins and a data input pin
+    ///
+    /// The load pin is assumed to be active low, as on the 74HC165. Use
+    /// [`with_load_polarity`](Self::with_load_polarity) for registers such as the CD4021.
+    pub fn new(clock: Clock, load: Load, data: Data) -> Self {
+        BitBang {
+            clock,
+            load,
+            data,
+            load_polarity: LoadPolarity::ActiveLow,
+        }
+    }
+
+    /// Set the level of the load pin which samples the parallel inputs
+    pub fn with_load_polarity(mut self, load_polarity: LoadPolarity) -> Self {
+        self.load_polarity = load_polarity;
+        self
+    }
+
+    /// Consume the transport and return the original clock, load, and data pins
+    pub fn release(self) -> (Clock, Load, Data) {
+        (self.clock, self.load, self.data)
+    }
+}
+
+impl<Clock, Load, Data> Transport for BitBang<Clock, Load, Data>
+where
+    Clock: OutputPin,
+    Load: OutputPin,
+    Data: InputPin,
+{
+    type Error = SRErr<Clock, Load, Data>;
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.load_polarity
+            .pulse(&mut self.load)
+            .map_err(SRError::LoadPinError)?;
+
+        for position in 0..bytes.len() * 8 {
+            let clock_error = |error| SRError::ClockPinError { error, position };
+            let data_error = |error| SRError::DataPinError { error, position };
+            if self.data.is_high().map_err(data_error)? {
+                bytes[position / 8] |= 0x80 >> (position % 8);
+            } else {
+                bytes[position / 8] &= !(0x80 >> (position % 8));
+            }
+            self.clock.set_high().map_err(clock_error)?;
+            self.clock.set_low().map_err(clock_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parallel-in serial-out shift register
+///
+/// Input `i` is parallel input `D(i % 8)` of the `i / 8`th register in the chain, counting from
+/// the register whose serial output is connected to the transport. The chain is read into `B`
+/// bytes, and `B` must be `N.div_ceil(8)`.
+pub struct ShiftRegister<T, const N: usize, const B: usize>
+where
+    T: Transport,
+{
+    transport: RefCell<T>,
+    read_mode: ReadMode,
+    input_state: RefCell<[bool; N]>,
+}
+
+impl<Clock, Load, Data, const N: usize, const B: usize>
+    ShiftRegister<BitBang<Clock, Load, Data>, N, B>
+where
+    Clock: OutputPin,
+    Load: OutputPin,
+    Data: InputPin,
+{
+    /// Creates a new PISO shift register from clock and load output pins and a data input pin
+    ///
+    /// The load pin is assumed to be active low, as on the 74HC165. Use
+    /// [`with_load_polarity`](Self::with_load_polarity) for registers such as the CD4021.
+    pub fn new(clock: Clock, load: Load, data: Data) -> Self {
+        Self::with_transport(BitBang::new(clock, load, data))
+    }
+
+    /// Set the level of the load pin which samples the parallel inputs
+    pub fn with_load_polarity(mut self, load_polarity: LoadPolarity) -> Self {
+        self.transport.get_mut().load_polarity = load_polarity;
+        self
+    }
+
+    /// Consume the shift register and return the original clock, load, and data pins
+    pub fn release(self) -> (Clock, Load, Data) {
+        self.into_transport().release()
+    }
+}
+
+impl<T, const N: usize, const B: usize> ShiftRegister<T, N, B>
+where
+    T: Transport,
+{
+    const BYTES: () = assert!(B == N.div_ceil(8), "B must be N.div_ceil(8)");
+
+    /// Creates a new PISO shift register which is read through `transport`
+    pub fn with_transport(transport: T) -> Self {
+        let () = Self::BYTES;
+        ShiftRegister {
+            transport: RefCell::new(transport),
+            read_mode: ReadMode::Live,
+            input_state: RefCell::new([false; N]),
+        }
+    }
+
+    /// Set how the input pins obtain their level, [`ReadMode::Live`] by default
+    pub fn with_read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// Get embedded-hal input pins to read the shift register inputs
+    pub fn decompose(&self) -> [ShiftRegisterPin<'_, T, N, B>; N] {
+        core::array::from_fn(|i| ShiftRegisterPin::<'_, T, N, B>::new(self, i))
+    }
+
+    /// Consume the shift register and return its transport
+    pub fn into_transport(self) -> T {
+        let Self {
+            transport,
+            read_mode: _,
+            input_state: _,
+        } = self;
+        transport.into_inner()
+    }
+
+    /// Load and shift in the whole chain, returning the level of every input
+    pub fn read(&self) -> Result<[bool; N], T::Error> {
+        self.update()?;
+        Ok(*self.input_state.borrow())
+    }
+
+    /// Return the level of every input as of the last load, without touching the pins
+    pub fn state(&self) -> [bool; N] {
+        *self.input_state.borrow()
+    }
+
+    fn update(&self) -> Result<(), T::Error> {
+        let mut bytes = [0u8; B];
+        self.transport.borrow_mut().read(&mut bytes)?;
+
+        let mut input_state = self.input_state.borrow_mut();
+        for (i, input) in input_state.iter_mut().enumerate() {
+            *input = bytes[i / 8] & (1 << (i % 8)) != 0;
+        }
+        Ok(())
+    }
+}
+
+/// Error type during update
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SRError<ClockErr, LoadErr, DataErr> {
+    /// Something wrong with the clock pin.
+    ClockPinError {
+        /// Error of the clock pin.
+        error: ClockErr,
+        /// Number of bits which had been shifted out when the pin failed.
+        position: usize,
+    },
+    /// Something wrong with the load pin.
+    LoadPinError(LoadErr),
+    /// Something wrong with the data pin.
+    DataPinError {
+        /// Error of the data pin.
+        error: DataErr,
+        /// Number of bits which had been shifted out when the pin failed.
+        position: usize,
+    },
+}
+
+impl<ClockErr, LoadErr, DataErr> SRError<ClockErr, LoadErr, DataErr> {
+    /// Return the step of the update which failed
+    pub fn operation(&self) -> Operation {
+        match self {
+            SRError::ClockPinError { .. } | SRError::DataPinError { .. } => Operation::Shift,
+            SRError::LoadPinError(_) => Operation::Load,
+        }
+    }
+
+    /// Return the number of bits which had been shifted out when shifting aborted
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            SRError::ClockPinError { position, .. } | SRError::DataPinError { position, .. } => {
+                Some(*position)
+            }
+            SRError::LoadPinError(_) => None,
+        }
+    }
+}
+
+impl<ClockErr, LoadErr, DataErr> digital::Error for SRError<ClockErr, LoadErr, DataErr>
+where
+    ClockErr: digital::Error,
+    LoadErr: digital::Error,
+    DataErr: digital::Error,
+{
+    fn kind(&self) -> digital::ErrorKind {
+        match self {
+            SRError::ClockPinError { error, .. } => error.kind(),
+            SRError::LoadPinError(error) => error.kind(),
+            SRError::DataPinError { error, .. } => error.kind(),
+        }
+    }
+}
+
+impl<ClockErr, LoadErr, DataErr> fmt::Display for SRError<ClockErr, LoadErr, DataErr>
+where
+    ClockErr: fmt::Debug,
+    LoadErr: fmt::Debug,
+    DataErr: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SRError::ClockPinError { error, position } => write!(
+                f,
+                "clock pin failed while {} bit {}: {:?}",
+                self.operation(),
+                position,
+                error
+            ),
+            SRError::LoadPinError(error) => {
+                write!(f, "load pin failed while {}: {:?}", self.operation(), error)
+            }
+            SRError::DataPinError { error, position } => write!(
+                f,
+                "data pin failed while {} bit {}: {:?}",
+                self.operation(),
+                position,
+                error
+            ),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Hardware SPI transports for parallel-in serial-out shift registers
+//!
+//! The serial output of the register is read through MISO. The 74HC165 presents its first bit as
+//! soon as the inputs are loaded and shifts on the rising edge of the clock, so the SPI
+//! peripheral should be configured for mode 0.
+
+use core::fmt;
+
+use hal::digital::{self, OutputPin};
+use hal::spi::{self, SpiBus, SpiDevice};
+
+use crate::error::Operation;
+
+use super::{LoadPolarity, Transport};
+
+/// Transport for a chain on an SPI device with a separate load output pin
+pub struct Device<D, Load>
+where
+    D: SpiDevice,
+    Load: OutputPin,
+{
+    device: D,
+    load: Load,
+    load_polarity: LoadPolarity,
+}
+
+impl<D, Load> Device<D, Load>
+where
+    D: SpiDevice,
+    Load: OutputPin,
+{
+    /// Creates a new transport from an SPI device and a load output pin
+    ///
+    /// The load pin is assumed to be active low, as on the 74HC165.
+    pub fn new(device: D, load: Load) -> Self {
+        Device {
+            device,
+            load,
+            load_polarity: LoadPolarity::ActiveLow,
+        }
+    }
+
+    /// Set the level of the load pin which samples the parallel inputs
+    pub fn with_load_polarity(mut self, load_polarity: LoadPolarity) -> Self {
+        self.load_polarity = load_polarity;
+        self
+    }
+
+    /// Consume the transport and return the original SPI device and load output pin
+    pub fn release(self) -> (D, Load) {
+        (self.device, self.load)
+    }
+}
+
+impl<D, Load> Transport for Device<D, Load>
+where
+    D: SpiDevice,
+    Load: OutputPin,
+{
+    type Error = SRError<D::Error, Load::Error>;
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.load_polarity
+            .pulse(&mut self.load)
+            .map_err(SRError::LoadPinError)?;
+        self.device.read(bytes).map_err(SRError::SpiError)
+    }
+}
+
+/// Transport for a chain on an SPI bus with a separate load output pin
+pub struct Bus<B, Load>
+where
+    B: SpiBus,
+    Load: OutputPin,
+{
+    bus: B,
+    load: Load,
+    load_polarity: LoadPolarity,
+}
+
+impl<B, Load> Bus<B, Load>
+where
+    B: SpiBus,
+    Load: OutputPin,
+{
+    /// Creates a new transport from an SPI bus and a load output pin
+    ///
+    /// The load pin is assumed to be active low, as on the 74HC165.
+    pub fn new(bus: B, load: Load) -> Self {
+        Bus {
+            bus,
+            load,
+            load_polarity: LoadPolarity::ActiveLow,
+        }
+    }
+
+    /// Set the level of the load pin which samples the parallel inputs
+    pub fn with_load_polarity(mut self, load_polarity: LoadPolarity) -> Self {
+        self.load_polarity = load_polarity;
+        self
+    }
+
+    /// Consume the transport and return the original SPI bus and load output pin
+    pub fn release(self) -> (B, Load) {
+        (self.bus, self.load)
+    }
+}
+
+impl<B, Load> Transport for Bus<B, Load>
+where
+    B: SpiBus,
+    Load: OutputPin,
+{
+    type Error = SRError<B::Error, Load::Error>;
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.load_polarity
+            .pulse(&mut self.load)
+            .map_err(SRError::LoadPinError)?;
+        self.bus.read(bytes).map_err(SRError::SpiError)?;
+        self.bus.flush().map_err(SRError::SpiError)
+    }
+}
+
+/// Error type during update over SPI
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SRError<SpiErr, LoadErr> {
+    /// Something wrong with the SPI transfer.
+    SpiError(SpiErr),
+    /// Something wrong with the load pin.
+    LoadPinError(LoadErr),
+}
+
+impl<SpiErr, LoadErr> SRError<SpiErr, LoadErr> {
+    /// Return the step of the update which failed
+    pub fn operation(&self) -> Operation {
+        match self {
+            SRError::SpiError(_) => Operation::Shift,
+            SRError::LoadPinError(_) => Operation::Load,
+        }
+    }
+}
+
+impl<SpiErr, LoadErr> digital::Error for SRError<SpiErr, LoadErr>
+where
+    SpiErr: spi::Error,
+    LoadErr: digital::Error,
+{
+    fn kind(&self) -> digital::ErrorKind {
+        match self {
+            SRError::SpiError(_) => digital::ErrorKind::Other,
+            SRError::LoadPinError(error) => error.kind(),
+        }
+    }
+}
+
+impl<SpiErr, LoadErr> fmt::Display for SRError<SpiErr, LoadErr>
+where
+    SpiErr: fmt::Debug,
+    LoadErr: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SRError::SpiError(error) => {
+                write!(
+                    f,
+                    "SPI transfer failed while {}: {:?}",
+                    self.operation(),
+                    error
+                )
+            }
+            SRError::LoadPinError(error) => {
+                write!(f, "load pin failed while {}: {:?}", self.operation(), error)
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Simulated 74HC595 and 74HC165 chains for testing on the host, enabled by the `sim` feature
+//!
+//! The simulated chips follow the pin levels edge by edge, so a shift register driven through
+//! their pins behaves as it would on a board. Tests can then check the parallel outputs, or feed
+//! parallel inputs, after any sequence of calls. Each model records the first protocol violation
+//! it sees, such as a clock edge while the latch is high.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use hal::digital::{ErrorType, InputPin, OutputPin};
+
+/// Misuse of the chip pins detected by a simulated chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// The shift clock rose while the latch was high, so the latch edge that ends the shift is
+    /// missing.
+    ClockWhileLatchHigh,
+    /// The shift clock rose while the reset pin was low, so the bit was lost.
+    ClockWhileReset,
+    /// The clock rose while the parallel inputs were being loaded, so no bit was shifted.
+    ClockWhileLoading,
+}
+
+#[derive(Clone, Copy)]
+enum Hc595Line {
+    Clock,
+    Latch,
+    Data,
+    OutputEnable,
+    Reset,
+}
+
+struct Hc595State<const CHIPS: usize> {
+    clock: bool,
+    latch: bool,
+    data: bool,
+    output_enable: bool,
+    reset: bool,
+    shift_stage: [u8; CHIPS],
+    storage: [u8; CHIPS],
+    violation: Option<Violation>,
+}
+
+/// Chain of `CHIPS` simulated 74HC595 serial-in parallel-out shift registers
+///
+/// Chip 0 takes its serial input from the data pin, and the serial output `QH'` of every chip
+/// feeds the next one. Bit `j` of byte `k` in the returned states is output `Q(j)` of chip `k`,
+/// the same layout as [`crate::sipo::ShiftRegister::write_bytes`]. All registers power up
+/// cleared, with the output enable pin low and the reset pin high.
+pub struct Hc595<const CHIPS: usize> {
+    state: RefCell<Hc595State<CHIPS>>,
+}
+
+/// Output pins driving a simulated 74HC595 chain
+pub struct Hc595Pins<'a, const CHIPS: usize> {
+    /// Shift clock, `SRCLK`.
+    pub clock: Hc595Pin<'a, CHIPS>,
+    /// Storage register clock, `RCLK`.
+    pub latch: Hc595Pin<'a, CHIPS>,
+    /// Serial input of chip 0, `SER`.
+    pub data: Hc595Pin<'a, CHIPS>,
+    /// Active-low output enable, `/OE`.
+    pub output_enable: Hc595Pin<'a, CHIPS>,
+    /// Active-low shift stage reset, `/SRCLR`.
+    pub reset: Hc595Pin<'a, CHIPS>,
+}
+
+/// Output pin connected to one line of a simulated 74HC595 chain
+pub struct Hc595Pin<'a, const CHIPS: usize> {
+    chain: &'a Hc595<CHIPS>,
+    line: Hc595Line,
+}
+
+impl<const CHIPS: usize> ErrorType for Hc595Pin<'_, CHIPS> {
+    type Error = Infallible;
+}
+
+impl<const CHIPS: usize> OutputPin for Hc595Pin<'_, CHIPS> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.chain.drive(self.line, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.chain.drive(self.line, true);
+        Ok(())
+    }
+}
+
+impl<const CHIPS: usize> Default for Hc595<CHIPS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CHIPS: usize> Hc595<CHIPS> {
+    /// Creates a new chain with every register cleared
+    pub const fn new() -> Self {
+        Hc595 {
+            state: RefCell::new(Hc595State {
+                clock: false,
+                latch: false,
+                data: false,
+                output_enable: false,
+                reset: true,
+                shift_stage: [0; CHIPS],
+                storage: [0; CHIPS],
+                violation: None,
+            }),
+        }
+    }
+
+    /// Get the pins which drive the chain
+    pub fn pins(&self) -> Hc595Pins<'_, CHIPS> {
+        let pin = |line| Hc595Pin { chain: self, line };
+        Hc595Pins {
+            clock: pin(Hc595Line::Clock),
+            latch: pin(Hc595Line::Latch),
+            data: pin(Hc595Line::Data),
+            output_enable: pin(Hc595Line::OutputEnable),
+            reset: pin(Hc595Line::Reset),
+        }
+    }
+
+    /// Return the levels of the parallel outputs, or `None` while they are disabled
+    pub fn outputs(&self) -> Option<[u8; CHIPS]> {
+        let state = self.state.borrow();
+        (!state.output_enable).then_some(state.storage)
+    }
+
+    /// Return the contents of the storage registers, whether or not the outputs are enabled
+    pub fn storage(&self) -> [u8; CHIPS] {
+        self.state.borrow().storage
+    }
+
+    /// Return the contents of the shift stages, which have not been latched yet
+    pub fn shift_stage(&self) -> [u8; CHIPS] {
+        self.state.borrow().shift_stage
+    }
+
+    /// Return the first protocol violation since the last call, if any
+    pub fn take_violation(&self) -> Option<Violation> {
+        self.state.borrow_mut().violation.take()
+    }
+
+    fn drive(&self, line: Hc595Line, level: bool) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        match line {
+            Hc595Line::Clock => {
+                let rising = level && !state.clock;
+                state.clock = level;
+                if !rising {
+                    return;
+                }
+                if !state.reset {
+                    state.violation.get_or_insert(Violation::ClockWhileReset);
+                    return;
+                }
+                if state.latch {
+                    state
+                        .violation
+                        .get_or_insert(Violation::ClockWhileLatchHigh);
+                }
+                let mut carry = state.data;
+                for stage in state.shift_stage.iter_mut() {
+                    let out = *stage & 0x80 != 0;
+                    *stage = (*stage << 1) | carry as u8;
+                    carry = out;
+                }
+            }
+            Hc595Line::Latch => {
+                if level && !state.latch {
+                    state.storage = state.shift_stage;
+                }
+                state.latch = level;
+            }
+            Hc595Line::Data => state.data = level,
+            Hc595Line::OutputEnable => state.output_enable = level,
+            Hc595Line::Reset => {
+                state.reset = level;
+                if !level {
+                    state.shift_stage = [0; CHIPS];
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Hc165Line {
+    Clock,
+    Load,
+}
+
+struct Hc165State<const CHIPS: usize> {
+    clock: bool,
+    load: bool,
+    inputs: [u8; CHIPS],
+    shift_stage: [u8; CHIPS],
+    violation: Option<Violation>,
+}
+
+/// Chain of `CHIPS` simulated 74HC165 parallel-in serial-out shift registers
+///
+/// The serial output `QH` of chip 0 drives the data pin, and every chip takes its serial input
+/// from the next one, with the serial input of the last chip tied low. Bit `j` of byte `k` in
+/// the inputs is input `D(j)` of chip `k`, the same layout as [`crate::piso::ShiftRegister`]
+/// uses. The clock inhibit pin is tied low, and the load pin starts high.
+pub struct Hc165<const CHIPS: usize> {
+    state: RefCell<Hc165State<CHIPS>>,
+}
+
+/// Pins connected to a simulated 74HC165 chain
+pub struct Hc165Pins<'a, const CHIPS: usize> {
+    /// Shift clock, `CLK`.
+    pub clock: Hc165Pin<'a, CHIPS>,
+    /// Active-low parallel load, `SH/LD`.
+    pub load: Hc165Pin<'a, CHIPS>,
+    /// Serial output of chip 0, `QH`.
+    pub data: Hc165Data<'a, CHIPS>,
+}
+
+/// Output pin connected to the clock or load line of a simulated 74HC165 chain
+pub struct Hc165Pin<'a, const CHIPS: usize> {
+    chain: &'a Hc165<CHIPS>,
+    line: Hc165Line,
+}
+
+/// Input pin connected to the serial output of a simulated 74HC165 chain
+pub struct Hc165Data<'a, const CHIPS: usize> {
+    chain: &'a Hc165<CHIPS>,
+}
+
+impl<const CHIPS: usize> ErrorType for Hc165Pin<'_, CHIPS> {
+    type Error = Infallible;
+}
+
+impl<const CHIPS: usize> OutputPin for Hc165Pin<'_, CHIPS> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.chain.drive(self.line, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.chain.drive(self.line, true);
+        Ok(())
+    }
+}
+
+impl<const CHIPS: usize> ErrorType for Hc165Data<'_, CHIPS> {
+    type Error = Infallible;
+}
+
+impl<const CHIPS: usize> InputPin for Hc165Data<'_, CHIPS> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.chain.serial_output())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.chain.serial_output())
+    }
+}
+
+impl<const CHIPS: usize> Default for Hc165<CHIPS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CHIPS: usize> Hc165<CHIPS> {
+    /// Creates a new chain with every input and register low
+    pub const fn new() -> Self {
+        Hc165 {
+            state: RefCell::new(Hc165State {
+                clock: false,
+                load: true,
+                inputs: [0; CHIPS],
+                shift_stage: [0; CHIPS],
+                violation: None,
+            }),
+        }
+    }
+
+    /// Get the pins which drive and read the chain
+    pub fn pins(&self) -> Hc165Pins<'_, CHIPS> {
+        Hc165Pins {
+            clock: Hc165Pin {
+                chain: self,
+                line: Hc165Line::Clock,
+            },
+            load: Hc165Pin {
+                chain: self,
+                line: Hc165Line::Load,
+            },
+            data: Hc165Data { chain: self },
+        }
+    }
+
+    /// Set the levels of the parallel inputs
+    pub fn set_inputs(&self, inputs: [u8; CHIPS]) {
+        let mut state = self.state.borrow_mut();
+        state.inputs = inputs;
+        if !state.load {
+            state.shift_stage = inputs;
+        }
+    }
+
+    /// Return the contents of the shift stages
+    pub fn shift_stage(&self) -> [u8; CHIPS] {
+        self.state.borrow().shift_stage
+    }
+
+    /// Return the first protocol violation since the last call, if any
+    pub fn take_violation(&self) -> Option<Violation> {
+        self.state.borrow_mut().violation.take()
+    }
+
+    fn serial_output(&self) -> bool {
+        self.state
+            .borrow()
+            .shift_stage
+            .first()
+            .is_some_and(|stage| stage & 0x80 != 0)
+    }
+
+    fn drive(&self, line: Hc165Line, level: bool) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        match line {
+            Hc165Line::Clock => {
+                let rising = level && !state.clock;
+                state.clock = level;
+                if !rising {
+                    return;
+                }
+                if !state.load {
+                    state.violation.get_or_insert(Violation::ClockWhileLoading);
+                    return;
+                }
+                let mut carry = false;
+                for stage in state.shift_stage.iter_mut().rev() {
+                    let out = *stage & 0x80 != 0;
+                    *stage = (*stage << 1) | carry as u8;
+                    carry = out;
+                }
+            }
+            Hc165Line::Load => {
+                state.load = level;
+                if !level {
+                    state.shift_stage = state.inputs;
+                }
+            }
+        }
+    }
+}
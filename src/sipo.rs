@@ -1,30 +1,55 @@
 //! Serial-in parallel-out shift register
 
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use core::fmt;
 
+use hal::delay::DelayNs;
 use hal::digital::{self, ErrorType};
+use hal::pwm::SetDutyCycle;
 
-use crate::hal::digital::OutputPin;
+use crate::error::Operation;
+use crate::hal::digital::{OutputPin, StatefulOutputPin};
+
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(target_has_atomic = "32")]
+pub mod atomic;
+pub mod pwm;
+#[cfg(feature = "critical-section")]
+pub mod shared;
+pub mod spi;
 
 type SRErr<Pin1, Pin2, Pin3> = SRError<<Pin1 as ErrorType>::Error, <Pin2 as ErrorType>::Error, <Pin3 as ErrorType>::Error>;
+type Err<T, Oe, Reset> = Error<<T as Transport>::Error, <Oe as OutputEnable>::Error, <Reset as ErrorType>::Error>;
 /// Output pin of the shift register
-pub struct ShiftRegisterPin<'a, Pin1, Pin2, Pin3, const N: usize>
+pub struct ShiftRegisterPin<'a, T, const N: usize, const B: usize, Oe = NoPin, Reset = NoPin>
 where
-    Pin1: OutputPin,
-    Pin2: OutputPin,
-    Pin3: OutputPin,
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
 {
-    shift_register: &'a ShiftRegister<Pin1, Pin2, Pin3, N>,
+    shift_register: &'a ShiftRegister<T, N, B, Oe, Reset>,
     index: usize,
 }
 
-impl<'a, Pin1, Pin2, Pin3, const N: usize> ShiftRegisterPin<'a, Pin1, Pin2, Pin3, N>
+/// Output pin of a shift register which lives for `'static`, for instance in a `StaticCell`
+///
+/// This has no lifetime parameter, so it can be stored in long-lived driver structs. It is what
+/// [`ShiftRegister::decompose`] returns for a `&'static ShiftRegister`. The shift register keeps
+/// its state in cells, so the pin is not `Send` and stays in the context which created it; to
+/// hand pins to other tasks or interrupt handlers, use `shared::StaticShiftRegisterPin` or
+/// `atomic::StaticShiftRegisterPin` instead.
+pub type StaticShiftRegisterPin<T, const N: usize, const B: usize, Oe = NoPin, Reset = NoPin> =
+    ShiftRegisterPin<'static, T, N, B, Oe, Reset>;
+
+impl<'a, T, const N: usize, const B: usize, Oe, Reset> ShiftRegisterPin<'a, T, N, B, Oe, Reset>
 where
-    Pin1: OutputPin,
-    Pin2: OutputPin,
-    Pin3: OutputPin,
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
 {
-    fn new(shift_register: &'a ShiftRegister<Pin1, Pin2, Pin3, N>, index: usize) -> Self {
+    fn new(shift_register: &'a ShiftRegister<T, N, B, Oe, Reset>, index: usize) -> Self {
         ShiftRegisterPin {
             shift_register,
             index,
@@ -32,19 +57,22 @@ where
     }
 }
 
-impl<Pin1, Pin2, Pin3, const N: usize> ErrorType for ShiftRegisterPin<'_, Pin1, Pin2, Pin3, N> 
-    where
-        Pin1: OutputPin,
-        Pin2: OutputPin,
-        Pin3: OutputPin,
+impl<T, const N: usize, const B: usize, Oe, Reset> ErrorType
+    for ShiftRegisterPin<'_, T, N, B, Oe, Reset>
+where
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
 {
-    type Error = SRErr<Pin1, Pin2, Pin3>;
+    type Error = Err<T, Oe, Reset>;
 }
-impl<Pin1, Pin2, Pin3, const N: usize> OutputPin for ShiftRegisterPin<'_, Pin1, Pin2, Pin3, N>
+
+impl<T, const N: usize, const B: usize, Oe, Reset> OutputPin
+    for ShiftRegisterPin<'_, T, N, B, Oe, Reset>
 where
-    Pin1: OutputPin,
-    Pin2: OutputPin,
-    Pin3: OutputPin,
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
 {
 
     fn set_low(&mut self) -> Result<(), Self::Error> {
@@ -58,49 +86,709 @@ where
     }
 }
 
-/// Serial-in parallel-out shift register
-pub struct ShiftRegister<Pin1, Pin2, Pin3, const N: usize>
+impl<T, const N: usize, const B: usize, Oe, Reset> StatefulOutputPin
+    for ShiftRegisterPin<'_, T, N, B, Oe, Reset>
+where
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
+{
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(get_output(
+            &self.shift_register.output_state.borrow(),
+            self.index,
+        ))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!get_output(
+            &self.shift_register.output_state.borrow(),
+            self.index,
+        ))
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        let index = self.index;
+        self.shift_register.change(|output_state| {
+            set_output(output_state, index, !get_output(output_state, index))
+        })
+    }
+}
+
+/// Moves the state of the outputs into the shift register chain
+pub trait Transport {
+    /// Error returned when the transfer fails
+    type Error: digital::Error;
+
+    /// Shift out the last `bits` bits of `bytes`, then latch them onto the outputs
+    ///
+    /// Bytes are shifted out first to last and most significant bit first, so the least
+    /// significant bit of the last byte ends up on output 0. Any bits before the last `bits` are
+    /// padding which a transport may either skip or shift out past the end of the chain.
+    fn write(&mut self, bytes: &[u8], bits: usize) -> Result<(), Self::Error>;
+
+    /// Latch the current content of the shift stage onto the outputs without shifting
+    fn latch(&mut self) -> Result<(), Self::Error>;
+
+    /// Wait as long as the reset pin must stay at each level of the pulse which clears the shift
+    /// stage
+    ///
+    /// Transports without a delay return at once.
+    fn wait_reset(&mut self) {}
+}
+
+/// Iterate over the last `bits` bits of `bytes` in the order a bit-banged transport shifts them
+/// out, as `(position, level, write_data)`
+///
+/// `position` counts the bits shifted before this one, and `write_data` is false when the data
+/// line already holds `level` from the previous bit, so that it isn't written again.
+fn shifted_bits(bytes: &[u8], bits: usize) -> impl Iterator<Item = (usize, bool, bool)> + '_ {
+    let mut data_level = None;
+    (bytes.len() * 8 - bits..bytes.len() * 8)
+        .enumerate()
+        .map(move |(position, bit)| {
+            let level = bytes[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            let write_data = data_level != Some(level);
+            data_level = Some(level);
+            (position, level, write_data)
+        })
+}
+
+/// Return output `index` of a state packed as expected by [`Transport::write`], with output `i`
+/// in bit `i % 8` of the `i / 8`th byte from the end
+fn get_output<const B: usize>(state: &[u8; B], index: usize) -> bool {
+    state[B - 1 - index / 8] & (1 << (index % 8)) != 0
+}
+
+/// Set output `index` of a state packed as expected by [`Transport::write`]
+fn set_output<const B: usize>(state: &mut [u8; B], index: usize, level: bool) {
+    if level {
+        state[B - 1 - index / 8] |= 1 << (index % 8);
+    } else {
+        state[B - 1 - index / 8] &= !(1 << (index % 8));
+    }
+}
+
+/// Copy `bytes`, packed as in [`ShiftRegister::write_bytes`], onto the first outputs of a state
+/// of `N` outputs packed as expected by [`Transport::write`]
+fn unpack<const N: usize, const B: usize>(state: &mut [u8; B], bytes: &[u8]) {
+    for (k, &byte) in bytes.iter().enumerate().take(B) {
+        // Bits past the last output are padding, which stays low
+        let outputs = (N - 8 * k).min(8);
+        state[B - 1 - k] = byte & (0xFF >> (8 - outputs));
+    }
+}
+
+/// Active-low output enable line of the shift register chain
+pub trait OutputEnable {
+    /// Error returned when the line can't be driven
+    type Error: digital::Error;
+
+    /// Let the registers drive their outputs
+    fn enable(&mut self) -> Result<(), Self::Error>;
+
+    /// Put the outputs of the registers in high impedance
+    fn disable(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<Pin> OutputEnable for Pin
+where
+    Pin: OutputPin,
+{
+    type Error = Pin::Error;
+
+    fn enable(&mut self) -> Result<(), Self::Error> {
+        self.set_low()
+    }
+
+    fn disable(&mut self) -> Result<(), Self::Error> {
+        self.set_high()
+    }
+}
+
+/// Minimum durations of the bit-banged signals, in nanoseconds
+///
+/// The defaults are the SN74HC595 timing requirements at VCC = 2 V from -40 to 85 °C, the slowest
+/// case in its datasheet, so they hold for any supply voltage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// Time the data pin is stable before the rising edge of the clock
+    pub data_setup_ns: u32,
+    /// Time the clock pin stays high
+    pub clock_high_ns: u32,
+    /// Time the clock pin stays low
+    pub clock_low_ns: u32,
+    /// Time the latch pin stays at each level around its rising edge
+    pub latch_pulse_ns: u32,
+    /// Time the reset pin stays low, and then high before the latch rises
+    pub reset_pulse_ns: u32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            data_setup_ns: 125,
+            clock_high_ns: 100,
+            clock_low_ns: 100,
+            latch_pulse_ns: 100,
+            reset_pulse_ns: 100,
+        }
+    }
+}
+
+/// Transport which bit-bangs the clock, latch, and data output pins
+///
+/// The pins are toggled back to back unless a delay is added with
+/// [`with_delay`](Self::with_delay).
+pub struct BitBang<Pin1, Pin2, Pin3, D = NoDelay>
 where
     Pin1: OutputPin,
     Pin2: OutputPin,
     Pin3: OutputPin,
+    D: DelayNs,
 {
-    clock: RefCell<Pin1>,
-    latch: RefCell<Pin2>,
-    data: RefCell<Pin3>,
-    output_state: RefCell<[bool; N]>,
+    clock: Pin1,
+    latch: Pin2,
+    data: Pin3,
+    delay: D,
+    timing: Timing,
 }
 
-impl<Pin1, Pin2, Pin3, const N: usize> ShiftRegister<Pin1, Pin2, Pin3, N>
+impl<Pin1, Pin2, Pin3> BitBang<Pin1, Pin2, Pin3>
 where
     Pin1: OutputPin,
     Pin2: OutputPin,
     Pin3: OutputPin,
 {
-    /// Creates a new SIPO shift register from clock, latch, and data output pins
+    /// Creates a new bit-banged transport from clock, latch, and data output pins
     pub fn new(clock: Pin1, latch: Pin2, data: Pin3) -> Self {
-        ShiftRegister {
-            clock: RefCell::new(clock),
-            latch: RefCell::new(latch),
-            data: RefCell::new(data),
-            output_state: RefCell::new([false; N]),
+        BitBang {
+            clock,
+            latch,
+            data,
+            delay: NoDelay,
+            timing: Timing::default(),
         }
     }
 
-    /// Get embedded-hal output pins to control the shift register outputs
-    pub fn decompose(&self) -> [ShiftRegisterPin<'_, Pin1, Pin2, Pin3, N>; N] {
-        core::array::from_fn(|i| ShiftRegisterPin::<'_, Pin1, Pin2, Pin3, N>::new(self, i))
+    /// Wait with `delay` so that the signals respect `timing`
+    pub fn with_delay<D>(self, delay: D, timing: Timing) -> BitBang<Pin1, Pin2, Pin3, D>
+    where
+        D: DelayNs,
+    {
+        BitBang {
+            clock: self.clock,
+            latch: self.latch,
+            data: self.data,
+            delay,
+            timing,
+        }
+    }
+
+    /// Consume the transport and return the original clock, latch, and data output pins
+    pub fn release(self) -> (Pin1, Pin2, Pin3) {
+        (self.clock, self.latch, self.data)
+    }
+}
+
+impl<Pin1, Pin2, Pin3, D> BitBang<Pin1, Pin2, Pin3, D>
+where
+    Pin1: OutputPin,
+    Pin2: OutputPin,
+    Pin3: OutputPin,
+    D: DelayNs,
+{
+    /// Remove the delay, returning it alongside the transport
+    pub fn without_delay(self) -> (BitBang<Pin1, Pin2, Pin3>, D) {
+        let transport = BitBang {
+            clock: self.clock,
+            latch: self.latch,
+            data: self.data,
+            delay: NoDelay,
+            timing: self.timing,
+        };
+        (transport, self.delay)
+    }
+
+    fn wait(&mut self, ns: u32) {
+        if ns > 0 {
+            self.delay.delay_ns(ns);
+        }
+    }
+}
+
+impl<Pin1, Pin2, Pin3, D> Transport for BitBang<Pin1, Pin2, Pin3, D>
+where
+    Pin1: OutputPin,
+    Pin2: OutputPin,
+    Pin3: OutputPin,
+    D: DelayNs,
+{
+    type Error = SRErr<Pin1, Pin2, Pin3>;
+
+    fn write(&mut self, bytes: &[u8], bits: usize) -> Result<(), Self::Error> {
+        self.latch.set_low().map_err(SRError::LatchPinError)?;
+        self.wait(self.timing.latch_pulse_ns);
+
+        for (position, level, write_data) in shifted_bits(bytes, bits) {
+            let data_error = |error| SRError::DataPinError { error, position };
+            let rise_error = |error| SRError::ClockPinError { error, position };
+            // Once the clock has risen, the bit has been shifted in
+            let fall_error = |error| SRError::ClockPinError {
+                error,
+                position: position + 1,
+            };
+            if write_data {
+                self.data.set_state(level.into()).map_err(data_error)?;
+            }
+            self.wait(self.timing.data_setup_ns);
+            self.clock.set_high().map_err(rise_error)?;
+            self.wait(self.timing.clock_high_ns);
+            self.clock.set_low().map_err(fall_error)?;
+            self.wait(self.timing.clock_low_ns);
+        }
+
+        self.latch.set_high().map_err(SRError::LatchPinError)?;
+        self.wait(self.timing.latch_pulse_ns);
+        Ok(())
+    }
+
+    fn latch(&mut self) -> Result<(), Self::Error> {
+        self.latch.set_low().map_err(SRError::LatchPinError)?;
+        self.wait(self.timing.latch_pulse_ns);
+        self.latch.set_high().map_err(SRError::LatchPinError)?;
+        self.wait(self.timing.latch_pulse_ns);
+        Ok(())
+    }
+
+    fn wait_reset(&mut self) {
+        self.wait(self.timing.reset_pulse_ns);
+    }
+}
+
+/// Order in which the outputs of each register are shifted out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Output `i` drives `Q(i % 8)`, so the highest output of each register is shifted first
+    MsbFirst,
+    /// Output `i` drives `Q(7 - i % 8)`, so the lowest output of each register is shifted first
+    LsbFirst,
+}
+
+/// Order in which the registers of the chain are numbered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterOrder {
+    /// Outputs 0 to 7 belong to the register whose serial input is connected to the transport
+    NearestFirst,
+    /// Outputs 0 to 7 belong to the register at the far end of the chain
+    FarthestFirst,
+}
+
+/// Defers writes while it lives, and restores the previous setting when dropped, even by a panic
+struct Deferral<'a> {
+    deferred: &'a Cell<bool>,
+    previous: bool,
+}
+
+impl<'a> Deferral<'a> {
+    fn new(deferred: &'a Cell<bool>) -> Self {
+        Deferral {
+            previous: deferred.replace(true),
+            deferred,
+        }
+    }
+}
+
+impl Drop for Deferral<'_> {
+    fn drop(&mut self) {
+        self.deferred.set(self.previous);
+    }
+}
+
+/// Serial-in parallel-out shift register
+///
+/// By default output `i` is output `Q(i % 8)` of the `i / 8`th register in the chain, counting
+/// from the register whose serial input is connected to the transport. This mapping can be
+/// changed with [`with_bit_order`](Self::with_bit_order) and
+/// [`with_register_order`](Self::with_register_order) to match the schematic.
+///
+/// The state of the outputs is stored packed in `B` bytes, and `B` must be `N.div_ceil(8)`.
+pub struct ShiftRegister<T, const N: usize, const B: usize, Oe = NoPin, Reset = NoPin>
+where
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
+{
+    transport: RefCell<T>,
+    output_state: RefCell<[u8; B]>,
+    inverted: [u8; B],
+    deferred: Cell<bool>,
+    bit_order: BitOrder,
+    register_order: RegisterOrder,
+    output_enable: Option<RefCell<Oe>>,
+    held: Cell<bool>,
+    synced: Cell<bool>,
+    reset: Option<RefCell<Reset>>,
+}
+
+impl<Pin1, Pin2, Pin3, const N: usize, const B: usize>
+    ShiftRegister<BitBang<Pin1, Pin2, Pin3>, N, B>
+where
+    Pin1: OutputPin,
+    Pin2: OutputPin,
+    Pin3: OutputPin,
+{
+    /// Creates a new SIPO shift register from clock, latch, and data output pins
+    pub fn new(clock: Pin1, latch: Pin2, data: Pin3) -> Self {
+        Self::with_transport(BitBang::new(clock, latch, data))
     }
 
     /// Consume the shift register and return the original clock, latch, and data output pins
     pub fn release(self) -> (Pin1, Pin2, Pin3) {
+        self.into_transport().release()
+    }
+}
+
+impl<T, const N: usize, const B: usize> ShiftRegister<T, N, B>
+where
+    T: Transport,
+{
+    const BYTES: () = assert!(B == N.div_ceil(8), "B must be N.div_ceil(8)");
+
+    /// Creates a new SIPO shift register which is written through `transport`
+    pub fn with_transport(transport: T) -> Self {
+        let () = Self::BYTES;
+        ShiftRegister {
+            transport: RefCell::new(transport),
+            output_state: RefCell::new([0; B]),
+            inverted: [0; B],
+            deferred: Cell::new(false),
+            bit_order: BitOrder::MsbFirst,
+            register_order: RegisterOrder::NearestFirst,
+            output_enable: None,
+            held: Cell::new(false),
+            // The outputs hold whatever the registers powered up with until the first write
+            synced: Cell::new(false),
+            reset: None,
+        }
+    }
+
+    /// Consume the shift register and return its transport
+    pub fn into_transport(self) -> T {
         let Self {
-            clock,
-            latch,
-            data,
+            transport,
             output_state: _,
+            inverted: _,
+            deferred: _,
+            bit_order: _,
+            register_order: _,
+            output_enable: _,
+            held: _,
+            synced: _,
+            reset: _,
         } = self;
-        (clock.into_inner(), latch.into_inner(), data.into_inner())
+        transport.into_inner()
+    }
+}
+
+impl<T, const N: usize, const B: usize, Reset> ShiftRegister<T, N, B, NoPin, Reset>
+where
+    T: Transport,
+    Reset: OutputPin,
+{
+    /// Add an active-low output enable, such as the `/OE` pin of the 74HC595
+    ///
+    /// This is either an output pin or a [`pwm::Dimmer`] to also control the brightness of the
+    /// outputs.
+    pub fn with_output_enable<Pin>(self, output_enable: Pin) -> ShiftRegister<T, N, B, Pin, Reset>
+    where
+        Pin: OutputEnable,
+    {
+        ShiftRegister {
+            transport: self.transport,
+            output_state: self.output_state,
+            inverted: self.inverted,
+            deferred: self.deferred,
+            bit_order: self.bit_order,
+            register_order: self.register_order,
+            output_enable: Some(RefCell::new(output_enable)),
+            held: self.held,
+            synced: self.synced,
+            reset: self.reset,
+        }
+    }
+}
+
+impl<T, const N: usize, const B: usize, Oe> ShiftRegister<T, N, B, Oe, NoPin>
+where
+    T: Transport,
+    Oe: OutputEnable,
+{
+    /// Add an active-low reset pin, such as the `/SRCLR` pin of the 74HC595, used by
+    /// [`clear`](Self::clear)
+    pub fn with_reset<Pin>(self, reset: Pin) -> ShiftRegister<T, N, B, Oe, Pin>
+    where
+        Pin: OutputPin,
+    {
+        ShiftRegister {
+            transport: self.transport,
+            output_state: self.output_state,
+            inverted: self.inverted,
+            deferred: self.deferred,
+            bit_order: self.bit_order,
+            register_order: self.register_order,
+            output_enable: self.output_enable,
+            held: self.held,
+            synced: self.synced,
+            reset: Some(RefCell::new(reset)),
+        }
+    }
+}
+
+impl<T, const N: usize, const B: usize, Oe, Reset> ShiftRegister<T, N, B, Oe, Reset>
+where
+    T: Transport,
+    Oe: OutputEnable,
+    Reset: OutputPin,
+{
+    /// Set the order in which the outputs of each register are shifted out, [`BitOrder::MsbFirst`]
+    /// by default
+    pub fn with_bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    /// Set the order in which the registers of the chain are numbered,
+    /// [`RegisterOrder::NearestFirst`] by default
+    pub fn with_register_order(mut self, register_order: RegisterOrder) -> Self {
+        self.register_order = register_order;
+        self
+    }
+
+    /// Mark outputs as active low, packed as in [`write_bytes`](Self::write_bytes)
+    ///
+    /// Setting an inverted output high drives the register output low and the other way round.
+    /// The stored state, and so [`StatefulOutputPin`] and the state getters, always report the
+    /// logical level.
+    pub fn with_inversion_mask(mut self, mask: &[u8]) -> Self {
+        unpack::<N, B>(&mut self.inverted, mask);
+        self
+    }
+
+    /// Remove the output enable pin, returning it alongside the shift register
+    pub fn without_output_enable(self) -> (ShiftRegister<T, N, B, NoPin, Reset>, Option<Oe>) {
+        let shift_register = ShiftRegister {
+            transport: self.transport,
+            output_state: self.output_state,
+            inverted: self.inverted,
+            deferred: self.deferred,
+            bit_order: self.bit_order,
+            register_order: self.register_order,
+            output_enable: None,
+            held: self.held,
+            synced: self.synced,
+            reset: self.reset,
+        };
+        (shift_register, self.output_enable.map(RefCell::into_inner))
+    }
+
+    /// Remove the reset pin, returning it alongside the shift register
+    pub fn without_reset(self) -> (ShiftRegister<T, N, B, Oe, NoPin>, Option<Reset>) {
+        let shift_register = ShiftRegister {
+            transport: self.transport,
+            output_state: self.output_state,
+            inverted: self.inverted,
+            deferred: self.deferred,
+            bit_order: self.bit_order,
+            register_order: self.register_order,
+            output_enable: self.output_enable,
+            held: self.held,
+            synced: self.synced,
+            reset: None,
+        };
+        (shift_register, self.reset.map(RefCell::into_inner))
+    }
+
+    /// Disable the outputs, and keep them disabled until the next update has shifted out the
+    /// whole chain
+    ///
+    /// The output enable pin should also be pulled high until this is called, so that the random
+    /// power-on state of the registers never reaches the outputs.
+    pub fn hold_outputs(&self) -> Result<(), Err<T, Oe, Reset>> {
+        self.disable_outputs()?;
+        self.held.set(true);
+        // The outputs are only enabled again after a write
+        self.synced.set(false);
+        Ok(())
+    }
+
+    /// Drive all outputs from the latched state, if there is an output enable pin
+    pub fn enable_outputs(&self) -> Result<(), Err<T, Oe, Reset>> {
+        if let Some(output_enable) = &self.output_enable {
+            output_enable
+                .borrow_mut()
+                .enable()
+                .map_err(Error::OutputEnablePinError)?;
+        }
+        self.held.set(false);
+        Ok(())
+    }
+
+    /// Blank all outputs without changing their stored state, if there is an output enable pin
+    pub fn disable_outputs(&self) -> Result<(), Err<T, Oe, Reset>> {
+        match &self.output_enable {
+            Some(output_enable) => output_enable
+                .borrow_mut()
+                .disable()
+                .map_err(Error::OutputEnablePinError),
+            None => Ok(()),
+        }
+    }
+
+    /// Set every output low at once, ignoring any ongoing [`batch`](Self::batch)
+    ///
+    /// With a reset pin this clears the shift stage by pulsing the reset pin and latches it,
+    /// without clocking in N bits. Without one, or when some outputs are inverted and so must be
+    /// driven high, the cleared state is shifted out instead.
+    pub fn clear(&self) -> Result<(), Err<T, Oe, Reset>> {
+        *self.output_state.borrow_mut() = [0; B];
+        match &self.reset {
+            Some(reset) if self.inverted == [0; B] => {
+                self.synced.set(false);
+                let mut reset = reset.borrow_mut();
+                let mut transport = self.transport.borrow_mut();
+                reset.set_low().map_err(Error::ResetPinError)?;
+                transport.wait_reset();
+                reset.set_high().map_err(Error::ResetPinError)?;
+                transport.wait_reset();
+                transport.latch().map_err(Error::TransportError)?;
+                self.synced.set(true);
+                if self.held.get() {
+                    self.enable_outputs()?;
+                }
+                Ok(())
+            }
+            _ => self.flush(),
+        }
+    }
+
+    /// Get embedded-hal output pins to control the shift register outputs
+    ///
+    /// Called on a `&'static ShiftRegister`, this returns [`StaticShiftRegisterPin`]s.
+    pub fn decompose(&self) -> [ShiftRegisterPin<'_, T, N, B, Oe, Reset>; N] {
+        core::array::from_fn(|i| ShiftRegisterPin::<'_, T, N, B, Oe, Reset>::new(self, i))
+    }
+
+    /// Change any number of outputs, then shift and latch them all at once
+    ///
+    /// While `f` runs, setting an output pin only changes the stored state, whichever handle it
+    /// is set through. The chain is written once after `f` returns, if any output changed, so
+    /// every change appears on the outputs at the same instant.
+    pub fn batch<R>(&self, f: impl FnOnce() -> R) -> Result<R, Err<T, Oe, Reset>> {
+        let deferral = Deferral::new(&self.deferred);
+        let result = f();
+        let deferred = deferral.previous;
+        drop(deferral);
+        if !deferred {
+            self.settle()?;
+        }
+        Ok(result)
+    }
+
+    /// Return whether the outputs are known to show the stored state
+    ///
+    /// This is false until the chain is first written, while changes are deferred by a
+    /// [`batch`](Self::batch), and after a write fails partway, when the registers hold a partly
+    /// shifted state that may have been latched. Every successful write shifts out the whole
+    /// stored state, so the next change of any output brings them back in sync.
+    pub fn is_synced(&self) -> bool {
+        self.synced.get()
+    }
+
+    /// Shift out the whole stored state if the outputs are not known to show it, ignoring any
+    /// ongoing [`batch`](Self::batch)
+    ///
+    /// This brings the outputs back in sync after a failed write without changing any output, and
+    /// enables held outputs if enabling them failed.
+    pub fn resync(&self) -> Result<(), Err<T, Oe, Reset>> {
+        self.settle()
+    }
+
+    /// Shift out the whole stored state even if the outputs should already show it, ignoring any
+    /// ongoing [`batch`](Self::batch)
+    ///
+    /// Setting outputs to the level they already have doesn't touch the chain, so this is for
+    /// when the registers may have changed behind the driver's back, for instance after a glitch
+    /// or a brown-out of their supply.
+    pub fn force_refresh(&self) -> Result<(), Err<T, Oe, Reset>> {
+        self.flush()
+    }
+
+    /// Set the outputs from `bytes` with a single shift
+    ///
+    /// Bit `j` of `bytes[k]` drives output `8 * k + j`, so each byte covers one register of the
+    /// chain starting from output 0. Outputs past the end of `bytes` keep their state, and bits
+    /// past the last output are ignored.
+    pub fn write_bytes(&self, bytes: &[u8]) -> Result<(), Err<T, Oe, Reset>> {
+        self.change(|output_state| unpack::<N, B>(output_state, bytes))
+    }
+
+    /// Set outputs 0 to 7 from the bits of `value` with a single shift, bit `i` driving output `i`
+    pub fn write_u8(&self, value: u8) -> Result<(), Err<T, Oe, Reset>> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Set outputs 0 to 15 from the bits of `value` with a single shift, bit `i` driving output `i`
+    pub fn write_u16(&self, value: u16) -> Result<(), Err<T, Oe, Reset>> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Set outputs 0 to 31 from the bits of `value` with a single shift, bit `i` driving output `i`
+    pub fn write_u32(&self, value: u32) -> Result<(), Err<T, Oe, Reset>> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Set outputs 0 to 63 from the bits of `value` with a single shift, bit `i` driving output `i`
+    pub fn write_u64(&self, value: u64) -> Result<(), Err<T, Oe, Reset>> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Set outputs 0 to 127 from the bits of `value` with a single shift, bit `i` driving output `i`
+    pub fn write_u128(&self, value: u128) -> Result<(), Err<T, Oe, Reset>> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Return the stored state of the outputs packed as in [`write_bytes`](Self::write_bytes)
+    ///
+    /// Bytes past the last output are zero.
+    pub fn state_bytes<const L: usize>(&self) -> [u8; L] {
+        let output_state = self.output_state.borrow();
+        core::array::from_fn(|k| if k < B { output_state[B - 1 - k] } else { 0 })
+    }
+
+    /// Return the stored state of outputs 0 to 7, output `i` in bit `i`
+    pub fn state_u8(&self) -> u8 {
+        u8::from_le_bytes(self.state_bytes())
+    }
+
+    /// Return the stored state of outputs 0 to 15, output `i` in bit `i`
+    pub fn state_u16(&self) -> u16 {
+        u16::from_le_bytes(self.state_bytes())
+    }
+
+    /// Return the stored state of outputs 0 to 31, output `i` in bit `i`
+    pub fn state_u32(&self) -> u32 {
+        u32::from_le_bytes(self.state_bytes())
+    }
+
+    /// Return the stored state of outputs 0 to 63, output `i` in bit `i`
+    pub fn state_u64(&self) -> u64 {
+        u64::from_le_bytes(self.state_bytes())
+    }
+
+    /// Return the stored state of outputs 0 to 127, output `i` in bit `i`
+    pub fn state_u128(&self) -> u128 {
+        u128::from_le_bytes(self.state_bytes())
     }
 
     fn update(
@@ -109,63 +797,280 @@ where
         command: bool,
     ) -> Result<
         (),
-        SRErr<Pin1, Pin2, Pin3>,
+        Err<T, Oe, Reset>,
     > {
-        self.output_state.borrow_mut()[index] = command;
+        self.change(|output_state| set_output(output_state, index, command))
+    }
+
+    fn change(&self, f: impl FnOnce(&mut [u8; B])) -> Result<(), Err<T, Oe, Reset>> {
+        {
+            let mut output_state = self.output_state.borrow_mut();
+            let previous = *output_state;
+            f(&mut output_state);
+            if *output_state != previous {
+                self.synced.set(false);
+            }
+        }
+        if self.deferred.get() {
+            return Ok(());
+        }
+        self.settle()
+    }
+
+    /// Finish any work left by an earlier write: shift out the stored state if the outputs may
+    /// not show it, and enable held outputs whose enabling failed
+    fn settle(&self) -> Result<(), Err<T, Oe, Reset>> {
+        if !self.synced.get() {
+            self.flush()
+        } else if self.held.get() {
+            self.enable_outputs()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&self) -> Result<(), Err<T, Oe, Reset>> {
         let output_state = self.output_state.borrow();
-        self.latch
-            .borrow_mut()
-            .set_low()
-            .map_err(SRError::LatchPinError)?;
-
-        for i in 1..=output_state.len() {
-            if output_state[output_state.len() - i] {
-                self.data
-                    .borrow_mut()
-                    .set_high()
-                    .map_err(SRError::DataPinError)?;
-            } else {
-                self.data
-                    .borrow_mut()
-                    .set_low()
-                    .map_err(SRError::DataPinError)?;
+
+        self.synced.set(false);
+        let mut transport = self.transport.borrow_mut();
+        let written = if self.bit_order == BitOrder::MsbFirst
+            && self.register_order == RegisterOrder::NearestFirst
+            && self.inverted == [0; B]
+        {
+            // The stored state is already laid out as the transport expects
+            transport.write(&*output_state, N)
+        } else {
+            let mut bytes = [0u8; B];
+            let mut bits = 0;
+            for i in 0..N {
+                let position = self.position(i);
+                let level = get_output(&output_state, i) != get_output(&self.inverted, i);
+                set_output(&mut bytes, position, level);
+                bits = bits.max(position + 1);
             }
-            self.clock
-                .borrow_mut()
-                .set_high()
-                .map_err(SRError::ClockPinError)?;
-            self.clock
+            transport.write(&bytes, bits)
+        };
+        written.map_err(Error::TransportError)?;
+        self.synced.set(true);
+
+        if self.held.get() {
+            self.enable_outputs()?;
+        }
+        Ok(())
+    }
+
+    /// Position of output `index` in the chain, counting from `Q0` of the nearest register
+    fn position(&self, index: usize) -> usize {
+        let register = match self.register_order {
+            RegisterOrder::NearestFirst => index / 8,
+            RegisterOrder::FarthestFirst => B - 1 - index / 8,
+        };
+        let bit = match self.bit_order {
+            BitOrder::MsbFirst => index % 8,
+            BitOrder::LsbFirst => 7 - index % 8,
+        };
+        register * 8 + bit
+    }
+}
+
+impl<T, const N: usize, const B: usize, P, Reset> ShiftRegister<T, N, B, pwm::Dimmer<P>, Reset>
+where
+    T: Transport,
+    P: SetDutyCycle,
+    Reset: OutputPin,
+{
+    /// Set the brightness of all outputs to `numerator / denominator` of full brightness
+    ///
+    /// The brightness is kept while the outputs are disabled and applies again once they are
+    /// enabled.
+    pub fn set_brightness_fraction(
+        &self,
+        numerator: u16,
+        denominator: u16,
+    ) -> Result<(), Err<T, pwm::Dimmer<P>, Reset>> {
+        match &self.output_enable {
+            Some(dimmer) => dimmer
                 .borrow_mut()
-                .set_low()
-                .map_err(SRError::ClockPinError)?;
+                .set_brightness_fraction(numerator, denominator)
+                .map_err(Error::OutputEnablePinError),
+            None => Ok(()),
         }
+    }
+
+    /// Set the brightness of all outputs in percent of full brightness
+    pub fn set_brightness_percent(&self, percent: u8) -> Result<(), Err<T, pwm::Dimmer<P>, Reset>> {
+        self.set_brightness_fraction(u16::from(percent), 100)
+    }
+}
 
-        self.latch
-            .borrow_mut()
-            .set_high()
-            .map_err(SRError::LatchPinError)?;
+/// Pin type for optional pins which are not connected
+pub struct NoPin;
+
+impl ErrorType for NoPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
 }
 
+/// Delay type for transports which toggle their pins without waiting
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Error type of the shift register operations
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<TransportErr, OutputEnableErr, ResetErr> {
+    /// Something wrong with the transport.
+    TransportError(TransportErr),
+    /// Something wrong with the output enable pin.
+    OutputEnablePinError(OutputEnableErr),
+    /// Something wrong with the reset pin.
+    ResetPinError(ResetErr),
+}
+
+impl<TransportErr, OutputEnableErr, ResetErr> digital::Error
+    for Error<TransportErr, OutputEnableErr, ResetErr>
+where
+    TransportErr: digital::Error,
+    OutputEnableErr: digital::Error,
+    ResetErr: digital::Error,
+{
+    fn kind(&self) -> digital::ErrorKind {
+        match self {
+            Error::TransportError(error) => error.kind(),
+            Error::OutputEnablePinError(error) => error.kind(),
+            Error::ResetPinError(error) => error.kind(),
+        }
+    }
+}
+
+impl<TransportErr, OutputEnableErr, ResetErr> fmt::Display
+    for Error<TransportErr, OutputEnableErr, ResetErr>
+where
+    TransportErr: fmt::Display,
+    OutputEnableErr: fmt::Debug,
+    ResetErr: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::TransportError(error) => error.fmt(f),
+            Error::OutputEnablePinError(error) => write!(
+                f,
+                "output enable pin failed while {}: {:?}",
+                Operation::OutputEnable,
+                error
+            ),
+            Error::ResetPinError(error) => {
+                write!(
+                    f,
+                    "reset pin failed while {}: {:?}",
+                    Operation::Clear,
+                    error
+                )
+            }
+        }
+    }
+}
+
 /// Error type during update
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SRError<Pin1Err, Pin2Err, Pin3Err> {
     /// Something wrong with the clock pin.
-    ClockPinError(Pin1Err),
+    ClockPinError {
+        /// Error of the clock pin.
+        error: Pin1Err,
+        /// Number of bits which had been shifted in when the pin failed.
+        position: usize,
+    },
     /// Something wrong with the latch pin.
     LatchPinError(Pin2Err),
     /// Something wrong with the data pin.
-    DataPinError(Pin3Err),
+    DataPinError {
+        /// Error of the data pin.
+        error: Pin3Err,
+        /// Number of bits which had been shifted in when the pin failed.
+        position: usize,
+    },
+}
+
+impl<Pin1Err, Pin2Err, Pin3Err> SRError<Pin1Err, Pin2Err, Pin3Err> {
+    /// Return the step of the update which failed
+    pub fn operation(&self) -> Operation {
+        match self {
+            SRError::ClockPinError { .. } | SRError::DataPinError { .. } => Operation::Shift,
+            SRError::LatchPinError(_) => Operation::Latch,
+        }
+    }
+
+    /// Return the number of bits which had been shifted in when shifting aborted
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            SRError::ClockPinError { position, .. } | SRError::DataPinError { position, .. } => {
+                Some(*position)
+            }
+            SRError::LatchPinError(_) => None,
+        }
+    }
 }
 
-impl<Pin1Err, Pin2Err, Pin3Err> digital::Error for SRError<Pin1Err, Pin2Err, Pin3Err> 
+impl<Pin1Err, Pin2Err, Pin3Err> digital::Error for SRError<Pin1Err, Pin2Err, Pin3Err>
 where
-    Pin1Err: core::fmt::Debug,
-    Pin2Err: core::fmt::Debug,
-    Pin3Err: core::fmt::Debug,
+    Pin1Err: digital::Error,
+    Pin2Err: digital::Error,
+    Pin3Err: digital::Error,
 {
     fn kind(&self) -> digital::ErrorKind {
-        digital::ErrorKind::Other
+        match self {
+            SRError::ClockPinError { error, .. } => error.kind(),
+            SRError::LatchPinError(error) => error.kind(),
+            SRError::DataPinError { error, .. } => error.kind(),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl<Pin1Err, Pin2Err, Pin3Err> fmt::Display for SRError<Pin1Err, Pin2Err, Pin3Err>
+where
+    Pin1Err: fmt::Debug,
+    Pin2Err: fmt::Debug,
+    Pin3Err: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SRError::ClockPinError { error, position } => write!(
+                f,
+                "clock pin failed while {} bit {}: {:?}",
+                self.operation(),
+                position,
+                error
+            ),
+            SRError::LatchPinError(error) => {
+                write!(
+                    f,
+                    "latch pin failed while {}: {:?}",
+                    self.operation(),
+                    error
+                )
+            }
+            SRError::DataPinError { error, position } => write!(
+                f,
+                "data pin failed while {} bit {}: {:?}",
+                self.operation(),
+                position,
+                error
+            ),
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Asynchronous serial-in parallel-out shift register, enabled by the `async` feature
+//!
+//! Output pins can be handed to different tasks of the same executor. A write requested while
+//! another task is already writing the chain waits for that task, which usually shifts out the
+//! change along with its own, so concurrent changes are coalesced instead of conflicting.
+
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use core::future;
+use core::task::{Poll, Waker};
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::SpiDevice;
+use hal::digital::{self, OutputPin};
+
+use super::{get_output, set_output, shifted_bits, spi, unpack, SRErr, SRError, Timing};
+
+/// Moves the state of the outputs into the shift register chain without blocking
+///
+/// This is the asynchronous counterpart of [`super::Transport`], with the same byte layout.
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    /// Error returned when the transfer fails
+    type Error: digital::Error;
+
+    /// Shift out the last `bits` bits of `bytes`, then latch them onto the outputs
+    async fn write(&mut self, bytes: &[u8], bits: usize) -> Result<(), Self::Error>;
+}
+
+/// Transport for a chain on an SPI device whose chip select line drives the latch
+pub struct Device<D>
+where
+    D: SpiDevice,
+{
+    device: D,
+}
+
+impl<D> Device<D>
+where
+    D: SpiDevice,
+{
+    /// Creates a new transport from an SPI device
+    pub fn new(device: D) -> Self {
+        Device { device }
+    }
+
+    /// Consume the transport and return the original SPI device
+    pub fn release(self) -> D {
+        self.device
+    }
+}
+
+impl<D> Transport for Device<D>
+where
+    D: SpiDevice,
+{
+    type Error = spi::SRError<D::Error, Infallible>;
+
+    async fn write(&mut self, bytes: &[u8], _bits: usize) -> Result<(), Self::Error> {
+        self.device
+            .write(bytes)
+            .await
+            .map_err(spi::SRError::SpiError)
+    }
+}
+
+/// Transport which bit-bangs the clock, latch, and data output pins, awaiting `delay` between
+/// edges so that other tasks run while the signals settle
+pub struct BitBang<Pin1, Pin2, Pin3, D>
+where
+    Pin1: OutputPin,
+    Pin2: OutputPin,
+    Pin3: OutputPin,
+    D: DelayNs,
+{
+    clock: Pin1,
+    latch: Pin2,
+    data: Pin3,
+    delay: D,
+    timing: Timing,
+}
+
+impl<Pin1, Pin2, Pin3, D> BitBang<Pin1, Pin2, Pin3, D>
+where
+    Pin1: OutputPin,
+    Pin2: OutputPin,
+    Pin3: OutputPin,
+    D: DelayNs,
+{
+    /// Creates a new transport from clock, latch, and data output pins with the default timing
+    pub fn new(clock: Pin1, latch: Pin2, data: Pin3, delay: D) -> Self {
+        BitBang {
+            clock,
+            latch,
+            data,
+            delay,
+            timing: Timing::default(),
+        }
+    }
+
+    /// Set the minimum durations of the signals
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Consume the transport and return the original clock, latch, and data output pins and the
+    /// delay
+    pub fn release(self) -> (Pin1, Pin2, Pin3, D) {
+        (self.clock, self.latch, self.data, self.delay)
+    }
+
+    async fn wait(&mut self, ns: u32) {
+        if ns > 0 {
+            self.delay.delay_ns(ns).await;
+        }
+    }
+}
+
+impl<Pin1, Pin2, Pin3, D> Transport for BitBang<Pin1, Pin2, Pin3, D>
+where
+    Pin1: OutputPin,
+    Pin2: OutputPin,
+    Pin3: OutputPin,
+    D: DelayNs,
+{
+    type Error = SRErr<Pin1, Pin2, Pin3>;
+
+    async fn write(&mut self, bytes: &[u8], bits: usize) -> Result<(), Self::Error> {
+        self.latch.set_low().map_err(SRError::LatchPinError)?;
+        self.wait(self.timing.latch_pulse_ns).await;
+
+        for (position, level, write_data) in shifted_bits(bytes, bits) {
+            let data_error = |error| SRError::DataPinError { error, position };
+            let rise_error = |error| SRError::ClockPinError { error, position };
+            // Once the clock has risen, the bit has been shifted in
+            let fall_error = |error| SRError::ClockPinError {
+                error,
+                position: position + 1,
+            };
+            if write_data {
+                self.data.set_state(level.into()).map_err(data_error)?;
+            }
+            self.wait(self.timing.data_setup_ns).await;
+            self.clock.set_high().map_err(rise_error)?;
+            self.wait(self.timing.clock_high_ns).await;
+            self.clock.set_low().map_err(fall_error)?;
+            self.wait(self.timing.clock_low_ns).await;
+        }
+
+        self.latch.set_high().map_err(SRError::LatchPinError)?;
+        self.wait(self.timing.latch_pulse_ns).await;
+        Ok(())
+    }
+}
+
+/// Output pin of the asynchronous shift register
+pub struct ShiftRegisterPin<'a, T, const N: usize, const B: usize>
+where
+    T: Transport,
+{
+    shift_register: &'a ShiftRegister<T, N, B>,
+    index: usize,
+}
+
+/// Output pin of an asynchronous shift register which lives for `'static`, as needed to pass it to
+/// a spawned task
+pub type StaticShiftRegisterPin<T, const N: usize, const B: usize> =
+    ShiftRegisterPin<'static, T, N, B>;
+
+impl<T, const N: usize, const B: usize> ShiftRegisterPin<'_, T, N, B>
+where
+    T: Transport,
+{
+    /// Drive the output high
+    pub async fn set_high(&mut self) -> Result<(), T::Error> {
+        self.shift_register.set(self.index, true).await
+    }
+
+    /// Drive the output low
+    pub async fn set_low(&mut self) -> Result<(), T::Error> {
+        self.shift_register.set(self.index, false).await
+    }
+
+    /// Invert the output
+    pub async fn toggle(&mut self) -> Result<(), T::Error> {
+        let level = !self.is_set_high();
+        self.shift_register.set(self.index, level).await
+    }
+
+    /// Return whether the output is set high
+    pub fn is_set_high(&self) -> bool {
+        get_output(&self.shift_register.output_state.borrow(), self.index)
+    }
+
+    /// Return whether the output is set low
+    pub fn is_set_low(&self) -> bool {
+        !self.is_set_high()
+    }
+}
+
+/// Asynchronous serial-in parallel-out shift register
+///
+/// Output `i` is output `Q(i % 8)` of the `i / 8`th register in the chain, counting from the
+/// register whose serial input is connected to the transport. The state of the outputs is stored
+/// packed in `B` bytes, and `B` must be `N.div_ceil(8)`.
+pub struct ShiftRegister<T, const N: usize, const B: usize>
+where
+    T: Transport,
+{
+    transport: RefCell<T>,
+    output_state: RefCell<[u8; B]>,
+    dirty: Cell<bool>,
+    waiters: Waiters,
+}
+
+impl<T, const N: usize, const B: usize> ShiftRegister<T, N, B>
+where
+    T: Transport,
+{
+    const BYTES: () = assert!(B == N.div_ceil(8), "B must be N.div_ceil(8)");
+
+    /// Creates a new asynchronous SIPO shift register which is written through `transport`
+    pub fn with_transport(transport: T) -> Self {
+        let () = Self::BYTES;
+        ShiftRegister {
+            transport: RefCell::new(transport),
+            output_state: RefCell::new([0; B]),
+            // The outputs hold whatever the registers powered up with until the first write
+            dirty: Cell::new(true),
+            waiters: Waiters::default(),
+        }
+    }
+
+    /// Get output pins to control the shift register outputs, possibly from different tasks
+    pub fn decompose(&self) -> [ShiftRegisterPin<'_, T, N, B>; N] {
+        core::array::from_fn(|index| ShiftRegisterPin {
+            shift_register: self,
+            index,
+        })
+    }
+
+    /// Consume the shift register and return its transport
+    pub fn into_transport(self) -> T {
+        self.transport.into_inner()
+    }
+
+    /// Set output `index` to `level` and write the chain
+    pub async fn set(&self, index: usize, level: bool) -> Result<(), T::Error> {
+        set_output(&mut self.output_state.borrow_mut(), index, level);
+        self.dirty.set(true);
+        self.flush().await
+    }
+
+    /// Set the outputs from `bytes` and write the chain
+    ///
+    /// The layout is the same as [`super::ShiftRegister::write_bytes`]: bit `j` of `bytes[k]`
+    /// drives output `8 * k + j`, outputs past the end of `bytes` keep their state, and bits past
+    /// the last output are ignored.
+    pub async fn write_bytes(&self, bytes: &[u8]) -> Result<(), T::Error> {
+        unpack::<N, B>(&mut self.output_state.borrow_mut(), bytes);
+        self.dirty.set(true);
+        self.flush().await
+    }
+
+    /// Write any change to the outputs which has not reached the chain yet
+    ///
+    /// If another task is writing the chain already, this waits until it is done. That task
+    /// shifts out any change made before it finishes, so this returns at once if nothing is left,
+    /// and otherwise writes the remaining changes itself, for instance after that write failed.
+    // The transport is only ever borrowed through `try_borrow_mut`, so holding it across an await
+    // can't make another task panic
+    #[allow(clippy::await_holding_refcell_ref)]
+    pub async fn flush(&self) -> Result<(), T::Error> {
+        let mut transport = future::poll_fn(|cx| match self.transport.try_borrow_mut() {
+            Ok(transport) => Poll::Ready(transport),
+            Err(_) => {
+                self.waiters.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await;
+        // Declared after the borrow so that it is dropped first, however this future ends
+        let _writing = Writing(&self.waiters);
+
+        while self.dirty.replace(false) {
+            // If the write fails, or this future is dropped partway through it, the chain may hold
+            // a partly shifted state which must be written again
+            let unwritten = Unwritten(&self.dirty);
+            // Other tasks may change the outputs while the copy is being written
+            let bytes = *self.output_state.borrow();
+
+            transport.write(&bytes, N).await?;
+            unwritten.written();
+        }
+        Ok(())
+    }
+}
+
+/// Number of tasks which can wait for the transport before they are all woken to poll again
+const WAITERS: usize = 4;
+
+/// Wakers of the tasks waiting for another task to finish writing the chain
+#[derive(Default)]
+struct Waiters {
+    wakers: RefCell<[Option<Waker>; WAITERS]>,
+}
+
+impl Waiters {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.borrow_mut();
+        if wakers.iter().flatten().any(|w| w.will_wake(waker)) {
+            return;
+        }
+        if let Some(slot) = wakers.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(waker.clone());
+            return;
+        }
+        // Out of room, so wake the other tasks, which register again if the transport is still busy
+        let woken = core::mem::take(&mut *wakers);
+        wakers[0] = Some(waker.clone());
+        drop(wakers);
+        woken.into_iter().flatten().for_each(Waker::wake);
+    }
+
+    fn wake_all(&self) {
+        let woken = core::mem::take(&mut *self.wakers.borrow_mut());
+        woken.into_iter().flatten().for_each(Waker::wake);
+    }
+}
+
+/// Wakes the tasks waiting for the transport when the write which holds it ends
+struct Writing<'a>(&'a Waiters);
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        self.0.wake_all();
+    }
+}
+
+/// Marks the outputs dirty again when dropped, unless the write it guards completed
+struct Unwritten<'a>(&'a Cell<bool>);
+
+impl Unwritten<'_> {
+    fn written(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for Unwritten<'_> {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
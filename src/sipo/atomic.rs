@@ -0,0 +1,148 @@
+//! Lock-free serial-in parallel-out shift register state with deferred writes
+//!
+//! Setting an output only updates a bitmap of atomic words and marks it dirty, which is cheap
+//! enough for interrupt handlers and never touches the hardware. The chain is written by a
+//! separate [`ShiftRegister::flush`], typically from the main loop or a timer, which owns the
+//! transport.
+
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use hal::digital::{ErrorType, OutputPin, StatefulOutputPin};
+
+use super::Transport;
+
+/// Output pin of the lock-free shift register
+pub struct ShiftRegisterPin<'a, const N: usize, const W: usize> {
+    shift_register: &'a ShiftRegister<N, W>,
+    index: usize,
+}
+
+/// Output pin of a lock-free shift register which lives for `'static`, typically in a `static`
+pub type StaticShiftRegisterPin<const N: usize, const W: usize> = ShiftRegisterPin<'static, N, W>;
+
+impl<const N: usize, const W: usize> ErrorType for ShiftRegisterPin<'_, N, W> {
+    type Error = Infallible;
+}
+
+impl<const N: usize, const W: usize> OutputPin for ShiftRegisterPin<'_, N, W> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.shift_register.set(self.index, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.shift_register.set(self.index, true);
+        Ok(())
+    }
+}
+
+impl<const N: usize, const W: usize> StatefulOutputPin for ShiftRegisterPin<'_, N, W> {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.shift_register.get(self.index))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.shift_register.get(self.index))
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        self.shift_register.toggle(self.index);
+        Ok(())
+    }
+}
+
+/// State of a serial-in parallel-out shift register chain which is written on demand
+///
+/// The `N` outputs are stored in `W` atomic words, and `W` must be `N.div_ceil(32)`. Output `i`
+/// is output `Q(i % 8)` of the `i / 8`th register in the chain, counting from the register whose
+/// serial input is connected to the transport.
+pub struct ShiftRegister<const N: usize, const W: usize> {
+    words: [AtomicU32; W],
+    dirty: AtomicBool,
+}
+
+impl<const N: usize, const W: usize> Default for ShiftRegister<N, W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, const W: usize> ShiftRegister<N, W> {
+    const WORDS: () = assert!(W == N.div_ceil(32), "W must be N.div_ceil(32)");
+
+    /// Creates a new shift register state with every output low, which can live in a `static`
+    pub const fn new() -> Self {
+        let () = Self::WORDS;
+        ShiftRegister {
+            words: [const { AtomicU32::new(0) }; W],
+            // The outputs hold whatever the registers powered up with until the first flush
+            dirty: AtomicBool::new(true),
+        }
+    }
+
+    /// Get embedded-hal output pins which only change the stored state
+    pub fn decompose(&self) -> [ShiftRegisterPin<'_, N, W>; N] {
+        core::array::from_fn(|index| ShiftRegisterPin {
+            shift_register: self,
+            index,
+        })
+    }
+
+    /// Set output `index` to `level` without writing the chain
+    pub fn set(&self, index: usize, level: bool) {
+        assert!(index < N);
+        let bit = 1 << (index % 32);
+        if level {
+            self.words[index / 32].fetch_or(bit, Ordering::Relaxed);
+        } else {
+            self.words[index / 32].fetch_and(!bit, Ordering::Relaxed);
+        }
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Return the stored state of output `index`
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < N);
+        self.words[index / 32].load(Ordering::Relaxed) & (1 << (index % 32)) != 0
+    }
+
+    /// Invert output `index` without writing the chain
+    pub fn toggle(&self, index: usize) {
+        assert!(index < N);
+        self.words[index / 32].fetch_xor(1 << (index % 32), Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Return whether some output changed since the chain was last written
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    /// Write the stored state through `transport` with a single shift and latch, if it changed
+    ///
+    /// Returns whether the chain was written. Outputs set while the chain is being written are
+    /// picked up by the next flush.
+    pub fn flush<T>(&self, transport: &mut T) -> Result<bool, T::Error>
+    where
+        T: Transport,
+    {
+        if !self.dirty.swap(false, Ordering::Acquire) {
+            return Ok(false);
+        }
+
+        // With the last word first and each word big-endian, output `i` lands in bit `i % 8` of
+        // the `i / 8`th byte from the end, as the transport expects
+        let mut words = [[0u8; 4]; W];
+        for (bytes, word) in words.iter_mut().rev().zip(&self.words) {
+            *bytes = word.load(Ordering::Relaxed).to_be_bytes();
+        }
+        let bytes = words.as_flattened();
+        let bytes = &bytes[bytes.len() - N.div_ceil(8)..];
+
+        transport.write(bytes, N).inspect_err(|_| {
+            self.dirty.store(true, Ordering::Release);
+        })?;
+        Ok(true)
+    }
+}
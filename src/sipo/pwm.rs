@@ -0,0 +1,97 @@
+//! Brightness control of serial-in parallel-out shift registers through their output enable pin
+
+use hal::digital;
+use hal::pwm::{self, SetDutyCycle};
+
+use super::OutputEnable;
+
+/// Output enable driven by a PWM channel, dimming every output of the chain at once
+///
+/// The output enable pin is active low, so the duty cycle of the channel is the fraction of time
+/// during which the outputs are off.
+pub struct Dimmer<P>
+where
+    P: SetDutyCycle,
+{
+    pwm: P,
+    duty_cycle: u16,
+    enabled: bool,
+}
+
+impl<P> Dimmer<P>
+where
+    P: SetDutyCycle,
+{
+    /// Creates a new dimmer at full brightness from a PWM channel
+    ///
+    /// The channel is left untouched until the outputs are enabled.
+    pub fn new(pwm: P) -> Self {
+        Dimmer {
+            pwm,
+            duty_cycle: 0,
+            enabled: false,
+        }
+    }
+
+    /// Consume the dimmer and return the original PWM channel
+    pub fn release(self) -> P {
+        self.pwm
+    }
+
+    /// Set the brightness of the outputs to `numerator / denominator` of full brightness
+    ///
+    /// The brightness only reaches the channel while the outputs are enabled.
+    pub fn set_brightness_fraction(
+        &mut self,
+        numerator: u16,
+        denominator: u16,
+    ) -> Result<(), PwmError<P::Error>> {
+        debug_assert!(denominator != 0);
+        debug_assert!(numerator <= denominator);
+        let max_duty_cycle = u32::from(self.pwm.max_duty_cycle());
+        let off = u32::from(denominator - numerator) * max_duty_cycle / u32::from(denominator);
+        self.duty_cycle = off as u16;
+        if self.enabled {
+            self.pwm.set_duty_cycle(self.duty_cycle).map_err(PwmError)?;
+        }
+        Ok(())
+    }
+
+    /// Set the brightness of the outputs in percent of full brightness
+    pub fn set_brightness_percent(&mut self, percent: u8) -> Result<(), PwmError<P::Error>> {
+        self.set_brightness_fraction(u16::from(percent), 100)
+    }
+}
+
+impl<P> OutputEnable for Dimmer<P>
+where
+    P: SetDutyCycle,
+{
+    type Error = PwmError<P::Error>;
+
+    fn enable(&mut self) -> Result<(), Self::Error> {
+        self.pwm.set_duty_cycle(self.duty_cycle).map_err(PwmError)?;
+        self.enabled = true;
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<(), Self::Error> {
+        self.pwm.set_duty_cycle_fully_on().map_err(PwmError)?;
+        self.enabled = false;
+        Ok(())
+    }
+}
+
+/// Error type of the PWM channel
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PwmError<E>(pub E);
+
+impl<E> digital::Error for PwmError<E>
+where
+    E: pwm::Error,
+{
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
@@ -0,0 +1,156 @@
+//! Interrupt-safe serial-in parallel-out shift register, enabled by the `critical-section` feature
+//!
+//! The transport and the state of the outputs live in a [`critical_section::Mutex`], so a
+//! `&'static ShiftRegister` can be shared between interrupt handlers and thread mode, and its
+//! output pins are `Send` whenever the transport is. Every update runs inside a critical section,
+//! so interrupts stay masked for as long as the chain takes to shift out.
+
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use hal::digital::{ErrorType, OutputPin, StatefulOutputPin};
+
+use super::{get_output, set_output, unpack, Transport};
+
+/// Output pin of the interrupt-safe shift register
+pub struct ShiftRegisterPin<'a, T, const N: usize, const B: usize>
+where
+    T: Transport,
+{
+    shift_register: &'a ShiftRegister<T, N, B>,
+    index: usize,
+}
+
+/// Output pin of an interrupt-safe shift register which lives for `'static`
+///
+/// This has no lifetime parameter and is `Send` whenever the transport is, so it can be moved
+/// into interrupt handlers and RTIC resources.
+pub type StaticShiftRegisterPin<T, const N: usize, const B: usize> =
+    ShiftRegisterPin<'static, T, N, B>;
+
+impl<T, const N: usize, const B: usize> ErrorType for ShiftRegisterPin<'_, T, N, B>
+where
+    T: Transport,
+{
+    type Error = T::Error;
+}
+
+impl<T, const N: usize, const B: usize> OutputPin for ShiftRegisterPin<'_, T, N, B>
+where
+    T: Transport,
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        let index = self.index;
+        self.shift_register
+            .change(|output_state| set_output(output_state, index, false))
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let index = self.index;
+        self.shift_register
+            .change(|output_state| set_output(output_state, index, true))
+    }
+}
+
+impl<T, const N: usize, const B: usize> StatefulOutputPin for ShiftRegisterPin<'_, T, N, B>
+where
+    T: Transport,
+{
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.shift_register.get(self.index))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.shift_register.get(self.index))
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        let index = self.index;
+        self.shift_register.change(|output_state| {
+            set_output(output_state, index, !get_output(output_state, index))
+        })
+    }
+}
+
+struct Inner<T, const N: usize, const B: usize> {
+    transport: T,
+    output_state: [u8; B],
+}
+
+/// Interrupt-safe serial-in parallel-out shift register
+///
+/// Output `i` is output `Q(i % 8)` of the `i / 8`th register in the chain, counting from the
+/// register whose serial input is connected to the transport. The state of the outputs is stored
+/// packed in `B` bytes, and `B` must be `N.div_ceil(8)`.
+pub struct ShiftRegister<T, const N: usize, const B: usize>
+where
+    T: Transport,
+{
+    inner: Mutex<RefCell<Inner<T, N, B>>>,
+}
+
+impl<T, const N: usize, const B: usize> ShiftRegister<T, N, B>
+where
+    T: Transport,
+{
+    const BYTES: () = assert!(B == N.div_ceil(8), "B must be N.div_ceil(8)");
+
+    /// Creates a new interrupt-safe SIPO shift register which is written through `transport`
+    pub const fn with_transport(transport: T) -> Self {
+        let () = Self::BYTES;
+        ShiftRegister {
+            inner: Mutex::new(RefCell::new(Inner {
+                transport,
+                output_state: [0; B],
+            })),
+        }
+    }
+
+    /// Get embedded-hal output pins to control the shift register outputs
+    pub fn decompose(&self) -> [ShiftRegisterPin<'_, T, N, B>; N] {
+        core::array::from_fn(|index| ShiftRegisterPin {
+            shift_register: self,
+            index,
+        })
+    }
+
+    /// Consume the shift register and return its transport
+    pub fn into_transport(self) -> T {
+        self.inner.into_inner().into_inner().transport
+    }
+
+    /// Set the outputs from `bytes` with a single shift
+    ///
+    /// The layout is the same as [`super::ShiftRegister::write_bytes`]: bit `j` of `bytes[k]`
+    /// drives output `8 * k + j`, outputs past the end of `bytes` keep their state, and bits past
+    /// the last output are ignored.
+    pub fn write_bytes(&self, bytes: &[u8]) -> Result<(), T::Error> {
+        self.change(|output_state| unpack::<N, B>(output_state, bytes))
+    }
+
+    /// Return the stored state of the outputs packed as in [`write_bytes`](Self::write_bytes)
+    ///
+    /// Bytes past the last output are zero.
+    pub fn state_bytes<const L: usize>(&self) -> [u8; L] {
+        let output_state = critical_section::with(|cs| self.inner.borrow_ref(cs).output_state);
+        core::array::from_fn(|k| if k < B { output_state[B - 1 - k] } else { 0 })
+    }
+
+    /// Return the stored state of output `index`
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < N);
+        critical_section::with(|cs| get_output(&self.inner.borrow_ref(cs).output_state, index))
+    }
+
+    fn change(&self, f: impl FnOnce(&mut [u8; B])) -> Result<(), T::Error> {
+        critical_section::with(|cs| {
+            let mut inner = self.inner.borrow_ref_mut(cs);
+            let Inner {
+                transport,
+                output_state,
+            } = &mut *inner;
+            f(output_state);
+            transport.write(output_state, N)
+        })
+    }
+}
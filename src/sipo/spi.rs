@@ -0,0 +1,164 @@
+//! Hardware SPI transports for serial-in parallel-out shift registers
+//!
+//! The 74HC595 samples its serial input on the rising edge of the clock, so the SPI peripheral
+//! should be configured for mode 0.
+
+use core::convert::Infallible;
+use core::fmt;
+
+use hal::digital::{self, OutputPin};
+use hal::spi::{self, SpiBus, SpiDevice};
+
+use crate::error::Operation;
+
+use super::Transport;
+
+/// Transport for a chain on an SPI device whose chip select line drives the latch
+///
+/// The chain is written in a single transfer, and the rising edge of chip select at its end
+/// latches the new state onto the outputs.
+pub struct Device<D>
+where
+    D: SpiDevice,
+{
+    device: D,
+}
+
+impl<D> Device<D>
+where
+    D: SpiDevice,
+{
+    /// Creates a new transport from an SPI device
+    pub fn new(device: D) -> Self {
+        Device { device }
+    }
+
+    /// Consume the transport and return the original SPI device
+    pub fn release(self) -> D {
+        self.device
+    }
+}
+
+impl<D> Transport for Device<D>
+where
+    D: SpiDevice,
+{
+    type Error = SRError<D::Error, Infallible>;
+
+    fn write(&mut self, bytes: &[u8], _bits: usize) -> Result<(), Self::Error> {
+        self.device.write(bytes).map_err(SRError::SpiError)
+    }
+
+    fn latch(&mut self) -> Result<(), Self::Error> {
+        // An empty transaction still toggles chip select
+        self.device.transaction(&mut []).map_err(SRError::SpiError)
+    }
+}
+
+/// Transport for a chain on an SPI bus with a separate latch output pin
+pub struct Bus<B, L>
+where
+    B: SpiBus,
+    L: OutputPin,
+{
+    bus: B,
+    latch: L,
+}
+
+impl<B, L> Bus<B, L>
+where
+    B: SpiBus,
+    L: OutputPin,
+{
+    /// Creates a new transport from an SPI bus and a latch output pin
+    pub fn new(bus: B, latch: L) -> Self {
+        Bus { bus, latch }
+    }
+
+    /// Consume the transport and return the original SPI bus and latch output pin
+    pub fn release(self) -> (B, L) {
+        (self.bus, self.latch)
+    }
+}
+
+impl<B, L> Transport for Bus<B, L>
+where
+    B: SpiBus,
+    L: OutputPin,
+{
+    type Error = SRError<B::Error, L::Error>;
+
+    fn write(&mut self, bytes: &[u8], _bits: usize) -> Result<(), Self::Error> {
+        self.latch.set_low().map_err(SRError::LatchPinError)?;
+        self.bus.write(bytes).map_err(SRError::SpiError)?;
+        self.bus.flush().map_err(SRError::SpiError)?;
+        self.latch.set_high().map_err(SRError::LatchPinError)?;
+        Ok(())
+    }
+
+    fn latch(&mut self) -> Result<(), Self::Error> {
+        self.latch.set_low().map_err(SRError::LatchPinError)?;
+        self.latch.set_high().map_err(SRError::LatchPinError)?;
+        Ok(())
+    }
+}
+
+/// Error type during update over SPI
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SRError<SpiErr, LatchErr> {
+    /// Something wrong with the SPI transfer.
+    SpiError(SpiErr),
+    /// Something wrong with the latch pin.
+    LatchPinError(LatchErr),
+}
+
+impl<SpiErr, LatchErr> SRError<SpiErr, LatchErr> {
+    /// Return the step of the update which failed
+    pub fn operation(&self) -> Operation {
+        match self {
+            SRError::SpiError(_) => Operation::Shift,
+            SRError::LatchPinError(_) => Operation::Latch,
+        }
+    }
+}
+
+impl<SpiErr, LatchErr> digital::Error for SRError<SpiErr, LatchErr>
+where
+    SpiErr: spi::Error,
+    LatchErr: digital::Error,
+{
+    fn kind(&self) -> digital::ErrorKind {
+        match self {
+            SRError::SpiError(_) => digital::ErrorKind::Other,
+            SRError::LatchPinError(error) => error.kind(),
+        }
+    }
+}
+
+impl<SpiErr, LatchErr> fmt::Display for SRError<SpiErr, LatchErr>
+where
+    SpiErr: fmt::Debug,
+    LatchErr: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SRError::SpiError(error) => {
+                write!(
+                    f,
+                    "SPI transfer failed while {}: {:?}",
+                    self.operation(),
+                    error
+                )
+            }
+            SRError::LatchPinError(error) => {
+                write!(
+                    f,
+                    "latch pin failed while {}: {:?}",
+                    self.operation(),
+                    error
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Recording of pin activity as a Value Change Dump, enabled by the `std` feature
+//!
+//! Wrap the clock, latch and data pins with [`Recorder::pin`] before handing them to a shift
+//! register, then export what they did with [`Recorder::write_vcd`]. The file opens in GTKWave
+//! or PulseView, next to a logic analyzer trace of the real board.
+
+use std::cell::RefCell;
+use std::io;
+use std::string::String;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use hal::delay::DelayNs;
+use hal::digital::{ErrorType, OutputPin};
+
+enum Clock {
+    Wall(Instant),
+    Virtual,
+}
+
+struct Inner {
+    clock: Clock,
+    now: u64,
+    signals: Vec<&'static str>,
+    changes: Vec<(u64, usize, bool)>,
+}
+
+impl Inner {
+    fn timestamp(&mut self) -> u64 {
+        match &self.clock {
+            Clock::Wall(start) => start.elapsed().as_nanos() as u64,
+            Clock::Virtual => {
+                self.now += 1;
+                self.now - 1
+            }
+        }
+    }
+}
+
+/// Timestamped record of every level driven on a set of pins
+pub struct Recorder {
+    inner: RefCell<Inner>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    /// Creates a recorder which timestamps changes in nanoseconds since its creation
+    pub fn new() -> Self {
+        Self::with_clock(Clock::Wall(Instant::now()))
+    }
+
+    /// Creates a recorder with a simulated clock, for waveforms which are the same on every run
+    ///
+    /// Each change takes one nanosecond, and time only advances further through
+    /// [`delay`](Self::delay).
+    pub fn with_virtual_time() -> Self {
+        Self::with_clock(Clock::Virtual)
+    }
+
+    fn with_clock(clock: Clock) -> Self {
+        Recorder {
+            inner: RefCell::new(Inner {
+                clock,
+                now: 0,
+                signals: Vec::new(),
+                changes: Vec::new(),
+            }),
+        }
+    }
+
+    /// Wrap `pin` so that every level driven on it is recorded as the signal `name`
+    pub fn pin<P>(&self, name: &'static str, pin: P) -> RecordingPin<'_, P>
+    where
+        P: OutputPin,
+    {
+        let mut inner = self.inner.borrow_mut();
+        inner.signals.push(name);
+        RecordingPin {
+            recorder: self,
+            signal: inner.signals.len() - 1,
+            pin,
+        }
+    }
+
+    /// Get a delay which advances the simulated clock, or sleeps with a wall clock
+    pub fn delay(&self) -> RecordingDelay<'_> {
+        RecordingDelay { recorder: self }
+    }
+
+    /// Forget every recorded change, keeping the signals
+    pub fn clear(&self) {
+        self.inner.borrow_mut().changes.clear();
+    }
+
+    /// Write the recording in the Value Change Dump format, with a timescale of 1 ns
+    pub fn write_vcd<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let inner = self.inner.borrow();
+        writeln!(writer, "$timescale 1ns $end")?;
+        writeln!(writer, "$scope module shift_register $end")?;
+        for (signal, name) in inner.signals.iter().enumerate() {
+            writeln!(writer, "$var wire 1 {} {} $end", identifier(signal), name)?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+
+        // Every signal is unknown until it is first driven
+        writeln!(writer, "$dumpvars")?;
+        for signal in 0..inner.signals.len() {
+            writeln!(writer, "x{}", identifier(signal))?;
+        }
+        writeln!(writer, "$end")?;
+
+        let mut time = None;
+        for &(timestamp, signal, level) in &inner.changes {
+            if time != Some(timestamp) {
+                writeln!(writer, "#{}", timestamp)?;
+                time = Some(timestamp);
+            }
+            writeln!(writer, "{}{}", level as u8, identifier(signal))?;
+        }
+        Ok(())
+    }
+
+    /// Return the recording in the Value Change Dump format
+    pub fn to_vcd(&self) -> String {
+        let mut vcd = Vec::new();
+        self.write_vcd(&mut vcd)
+            .expect("writing to a Vec never fails");
+        String::from_utf8(vcd).expect("the dump is ASCII")
+    }
+
+    fn record(&self, signal: usize, level: bool) {
+        let mut inner = self.inner.borrow_mut();
+        let timestamp = inner.timestamp();
+        inner.changes.push((timestamp, signal, level));
+    }
+}
+
+/// Short identifier of a signal, made of the printable ASCII characters VCD allows
+fn identifier(mut signal: usize) -> String {
+    let mut identifier = String::new();
+    loop {
+        identifier.push((b'!' + (signal % 94) as u8) as char);
+        signal /= 94;
+        if signal == 0 {
+            return identifier;
+        }
+        signal -= 1;
+    }
+}
+
+/// Output pin whose levels are recorded by a [`Recorder`]
+pub struct RecordingPin<'a, P>
+where
+    P: OutputPin,
+{
+    recorder: &'a Recorder,
+    signal: usize,
+    pin: P,
+}
+
+impl<P> RecordingPin<'_, P>
+where
+    P: OutputPin,
+{
+    /// Stop recording and return the original pin
+    pub fn release(self) -> P {
+        self.pin
+    }
+}
+
+impl<P> ErrorType for RecordingPin<'_, P>
+where
+    P: OutputPin,
+{
+    type Error = P::Error;
+}
+
+impl<P> OutputPin for RecordingPin<'_, P>
+where
+    P: OutputPin,
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_low()?;
+        self.recorder.record(self.signal, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_high()?;
+        self.recorder.record(self.signal, true);
+        Ok(())
+    }
+}
+
+/// Delay which keeps the time of a [`Recorder`]
+pub struct RecordingDelay<'a> {
+    recorder: &'a Recorder,
+}
+
+impl DelayNs for RecordingDelay<'_> {
+    fn delay_ns(&mut self, ns: u32) {
+        let mut inner = self.recorder.inner.borrow_mut();
+        match inner.clock {
+            Clock::Wall(_) => std::thread::sleep(Duration::from_nanos(ns.into())),
+            Clock::Virtual => inner.now += u64::from(ns),
+        }
+    }
+}
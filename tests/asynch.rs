@@ -0,0 +1,156 @@
+#![cfg(feature = "async")]
+
+use std::cell::{Cell, RefCell};
+use std::convert::Infallible;
+use std::future::{self, Future};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use embedded_hal::digital::ErrorKind;
+use shift_register_driver::sipo::asynch::{ShiftRegister, Transport};
+
+/// Transport which records every complete write, and never finishes the first one
+struct StallingTransport<'a> {
+    stall: bool,
+    written: &'a RefCell<Vec<Vec<u8>>>,
+}
+
+impl Transport for StallingTransport<'_> {
+    type Error = Infallible;
+
+    async fn write(&mut self, bytes: &[u8], _bits: usize) -> Result<(), Self::Error> {
+        if self.stall {
+            self.stall = false;
+            future::pending::<()>().await;
+        }
+        self.written.borrow_mut().push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Transport whose writes only complete while the gate is open, and which can fail a write
+struct GatedTransport<'a> {
+    open: &'a Cell<bool>,
+    fail: &'a Cell<bool>,
+    written: &'a RefCell<Vec<Vec<u8>>>,
+}
+
+impl Transport for GatedTransport<'_> {
+    type Error = ErrorKind;
+
+    async fn write(&mut self, bytes: &[u8], _bits: usize) -> Result<(), Self::Error> {
+        future::poll_fn(|_| {
+            if self.open.get() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        if self.fail.replace(false) {
+            return Err(ErrorKind::Other);
+        }
+        self.written.borrow_mut().push(bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// Waker which records that it was woken
+#[derive(Default)]
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn dropped_write_is_written_again() {
+    let written = RefCell::new(Vec::new());
+    let shift_register = ShiftRegister::<_, 8, 1>::with_transport(StallingTransport {
+        stall: true,
+        written: &written,
+    });
+    let mut cx = Context::from_waker(Waker::noop());
+
+    {
+        let set = pin!(shift_register.set(3, true));
+        assert!(set.poll(&mut cx).is_pending());
+    }
+    assert!(written.borrow().is_empty());
+
+    let flush = pin!(shift_register.flush());
+    assert!(matches!(flush.poll(&mut cx), Poll::Ready(Ok(()))));
+    assert_eq!(*written.borrow(), [[0b1000]]);
+}
+
+#[test]
+fn contended_set_waits_for_the_write() {
+    let (open, fail, written) = (Cell::new(false), Cell::new(false), RefCell::new(Vec::new()));
+    let shift_register = ShiftRegister::<_, 8, 1>::with_transport(GatedTransport {
+        open: &open,
+        fail: &fail,
+        written: &written,
+    });
+    let mut cx = Context::from_waker(Waker::noop());
+    let woken = Arc::new(Woken::default());
+    let waker = Waker::from(woken.clone());
+    let mut second_cx = Context::from_waker(&waker);
+
+    let mut first = pin!(shift_register.set(0, true));
+    let mut second = pin!(shift_register.set(1, true));
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut second_cx).is_pending());
+
+    open.set(true);
+    assert_eq!(first.poll(&mut cx), Poll::Ready(Ok(())));
+    assert!(woken.0.load(Ordering::Relaxed));
+    assert_eq!(second.poll(&mut second_cx), Poll::Ready(Ok(())));
+    assert_eq!(*written.borrow(), [[0b1], [0b11]]);
+}
+
+#[test]
+fn contended_set_writes_after_a_failed_write() {
+    let (open, fail, written) = (Cell::new(false), Cell::new(true), RefCell::new(Vec::new()));
+    let shift_register = ShiftRegister::<_, 8, 1>::with_transport(GatedTransport {
+        open: &open,
+        fail: &fail,
+        written: &written,
+    });
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut first = pin!(shift_register.set(0, true));
+    let mut second = pin!(shift_register.set(1, true));
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+
+    open.set(true);
+    assert_eq!(first.poll(&mut cx), Poll::Ready(Err(ErrorKind::Other)));
+    assert_eq!(second.poll(&mut cx), Poll::Ready(Ok(())));
+    assert_eq!(*written.borrow(), [[0b11]]);
+}
+
+#[test]
+fn contended_set_writes_after_a_cancelled_write() {
+    let (open, fail, written) = (Cell::new(false), Cell::new(false), RefCell::new(Vec::new()));
+    let shift_register = ShiftRegister::<_, 8, 1>::with_transport(GatedTransport {
+        open: &open,
+        fail: &fail,
+        written: &written,
+    });
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut second = pin!(shift_register.set(1, true));
+    {
+        let first = pin!(shift_register.set(0, true));
+        assert!(first.poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+    }
+
+    open.set(true);
+    assert_eq!(second.poll(&mut cx), Poll::Ready(Ok(())));
+    assert_eq!(*written.borrow(), [[0b11]]);
+}
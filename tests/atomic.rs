@@ -0,0 +1,39 @@
+use std::convert::Infallible;
+
+use shift_register_driver::sipo::atomic::ShiftRegister;
+use shift_register_driver::sipo::Transport;
+
+/// Transport which records every write
+#[derive(Default)]
+struct RecordingTransport {
+    written: Vec<(Vec<u8>, usize)>,
+}
+
+impl Transport for RecordingTransport {
+    type Error = Infallible;
+
+    fn write(&mut self, bytes: &[u8], bits: usize) -> Result<(), Self::Error> {
+        self.written.push((bytes.to_vec(), bits));
+        Ok(())
+    }
+
+    fn latch(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[test]
+fn flush_packs_the_words_for_the_transport() {
+    let shift_register = ShiftRegister::<44, 2>::new();
+    let mut transport = RecordingTransport::default();
+    for index in [0, 9, 31, 32, 43] {
+        shift_register.set(index, true);
+    }
+
+    assert!(shift_register.flush(&mut transport).unwrap());
+    assert!(!shift_register.flush(&mut transport).unwrap());
+    assert_eq!(
+        transport.written,
+        [(vec![0b1000, 0b1, 0b1000_0000, 0, 0b10, 0b1], 44)]
+    );
+}
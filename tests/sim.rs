@@ -0,0 +1,146 @@
+#![cfg(feature = "sim")]
+
+use embedded_hal::digital::OutputPin;
+use shift_register_driver::piso;
+use shift_register_driver::sim::{Hc165, Hc595, Violation};
+use shift_register_driver::sipo::{BitOrder, RegisterOrder, ShiftRegister};
+
+#[test]
+fn default_mapping() {
+    let chain = Hc595::<2>::new();
+    let pins = chain.pins();
+    let shift_register = ShiftRegister::<_, 16, 2>::new(pins.clock, pins.latch, pins.data);
+
+    {
+        let mut outputs = shift_register.decompose();
+        outputs[0].set_high().unwrap();
+        outputs[9].set_high().unwrap();
+        outputs[15].set_high().unwrap();
+    }
+
+    assert_eq!(chain.outputs(), Some([0b1, 0b1000_0010]));
+    assert_eq!(chain.take_violation(), None);
+}
+
+#[test]
+fn bit_and_register_order() {
+    for (bit_order, register_order, expected) in [
+        (BitOrder::MsbFirst, RegisterOrder::NearestFirst, [0b10, 0b1]),
+        (
+            BitOrder::LsbFirst,
+            RegisterOrder::NearestFirst,
+            [0b100_0000, 0b1000_0000],
+        ),
+        (
+            BitOrder::MsbFirst,
+            RegisterOrder::FarthestFirst,
+            [0b1, 0b10],
+        ),
+        (
+            BitOrder::LsbFirst,
+            RegisterOrder::FarthestFirst,
+            [0b1000_0000, 0b100_0000],
+        ),
+    ] {
+        let chain = Hc595::<2>::new();
+        let pins = chain.pins();
+        let shift_register = ShiftRegister::<_, 16, 2>::new(pins.clock, pins.latch, pins.data)
+            .with_bit_order(bit_order)
+            .with_register_order(register_order);
+
+        shift_register.write_u16(0b1_0000_0010).unwrap();
+
+        assert_eq!(
+            chain.outputs(),
+            Some(expected),
+            "{:?} and {:?}",
+            bit_order,
+            register_order
+        );
+    }
+}
+
+#[test]
+fn inversion_mask() {
+    let chain = Hc595::<2>::new();
+    let pins = chain.pins();
+    let shift_register = ShiftRegister::<_, 16, 2>::new(pins.clock, pins.latch, pins.data)
+        .with_inversion_mask(&[0b1, 0b1000_0000]);
+
+    shift_register.write_u16(0b10).unwrap();
+    assert_eq!(chain.outputs(), Some([0b11, 0b1000_0000]));
+
+    shift_register.write_u16(0b1000_0000_0000_0001).unwrap();
+    assert_eq!(chain.outputs(), Some([0, 0]));
+    assert_eq!(shift_register.state_u16(), 0b1000_0000_0000_0001);
+}
+
+#[test]
+fn clear_with_reset_pin() {
+    let chain = Hc595::<2>::new();
+    let pins = chain.pins();
+    let shift_register =
+        ShiftRegister::<_, 16, 2>::new(pins.clock, pins.latch, pins.data).with_reset(pins.reset);
+
+    shift_register.write_u16(0xFFFF).unwrap();
+    assert_eq!(chain.outputs(), Some([0xFF, 0xFF]));
+
+    shift_register.clear().unwrap();
+    assert_eq!(chain.shift_stage(), [0, 0]);
+    assert_eq!(chain.outputs(), Some([0, 0]));
+    assert_eq!(shift_register.state_u16(), 0);
+    assert!(shift_register.is_synced());
+    assert_eq!(chain.take_violation(), None);
+}
+
+#[test]
+fn held_outputs() {
+    let chain = Hc595::<1>::new();
+    let pins = chain.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(pins.clock, pins.latch, pins.data)
+        .with_output_enable(pins.output_enable);
+    shift_register.hold_outputs().unwrap();
+    assert_eq!(chain.outputs(), None);
+
+    shift_register.write_u8(0b101).unwrap();
+    assert_eq!(chain.outputs(), Some([0b101]));
+
+    shift_register.disable_outputs().unwrap();
+    assert_eq!(chain.outputs(), None);
+    assert_eq!(chain.storage(), [0b101]);
+}
+
+#[test]
+fn read_inputs() {
+    let chain = Hc165::<2>::new();
+    chain.set_inputs([0b1000_0001, 0b100_0000]);
+    let pins = chain.pins();
+    let shift_register = piso::ShiftRegister::<_, 16, 2>::new(pins.clock, pins.load, pins.data);
+
+    let inputs = shift_register.read().unwrap();
+
+    let high: Vec<usize> = (0..16).filter(|&i| inputs[i]).collect();
+    assert_eq!(high, [0, 7, 14]);
+    assert_eq!(chain.take_violation(), None);
+}
+
+#[test]
+fn violations() {
+    let chain = Hc595::<1>::new();
+    let mut pins = chain.pins();
+    pins.latch.set_high().unwrap();
+    pins.clock.set_high().unwrap();
+    assert_eq!(chain.take_violation(), Some(Violation::ClockWhileLatchHigh));
+    assert_eq!(chain.take_violation(), None);
+
+    pins.clock.set_low().unwrap();
+    pins.reset.set_low().unwrap();
+    pins.clock.set_high().unwrap();
+    assert_eq!(chain.take_violation(), Some(Violation::ClockWhileReset));
+
+    let chain = Hc165::<1>::new();
+    let mut pins = chain.pins();
+    pins.load.set_low().unwrap();
+    pins.clock.set_high().unwrap();
+    assert_eq!(chain.take_violation(), Some(Violation::ClockWhileLoading));
+}
@@ -0,0 +1,551 @@
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::io::ErrorKind;
+use std::panic::{self, AssertUnwindSafe};
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{self, Error as _, ErrorType, OutputPin, StatefulOutputPin};
+use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction};
+use embedded_hal_mock::eh1::MockError;
+use shift_register_driver::error::Operation;
+use shift_register_driver::sipo::{BitBang, Error, SRError, ShiftRegister, Timing};
+
+/// Pin transactions expected on the clock, latch and data pins of a bit-banged chain
+#[derive(Default)]
+struct Expectations {
+    clock: Vec<Transaction>,
+    latch: Vec<Transaction>,
+    data: Vec<Transaction>,
+}
+
+impl Expectations {
+    /// Expect a complete write of `outputs`, where output 0 is shifted in last
+    ///
+    /// The data pin is only expected to be set when its level changes.
+    fn write(mut self, outputs: &[bool]) -> Self {
+        self.latch.push(Transaction::set(State::Low));
+        let mut data_level = None;
+        for &output in outputs.iter().rev() {
+            if data_level != Some(output) {
+                self.data.push(Transaction::set(level(output)));
+                data_level = Some(output);
+            }
+            self.clock.push(Transaction::set(State::High));
+            self.clock.push(Transaction::set(State::Low));
+        }
+        self.latch.push(Transaction::set(State::High));
+        self
+    }
+
+    fn pins(&self) -> (PinMock, PinMock, PinMock) {
+        (
+            PinMock::new(&self.clock),
+            PinMock::new(&self.latch),
+            PinMock::new(&self.data),
+        )
+    }
+}
+
+fn level(high: bool) -> State {
+    if high {
+        State::High
+    } else {
+        State::Low
+    }
+}
+
+fn error() -> MockError {
+    MockError::Io(ErrorKind::NotConnected)
+}
+
+fn done((mut clock, mut latch, mut data): (PinMock, PinMock, PinMock)) {
+    clock.done();
+    latch.done();
+    data.done();
+}
+
+/// Output pin which counts how often it is set
+struct CountingPin<'a>(&'a Cell<usize>);
+
+impl ErrorType for CountingPin<'_> {
+    type Error = Infallible;
+}
+
+impl OutputPin for CountingPin<'_> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set(self.0.get() + 1);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set(self.0.get() + 1);
+        Ok(())
+    }
+}
+
+/// Delay which adds up the time it waited
+struct TotalDelay<'a>(&'a Cell<u32>);
+
+impl DelayNs for TotalDelay<'_> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.set(self.0.get() + ns);
+    }
+}
+
+/// Outputs of an `N` output chain where only `index` is high
+fn only<const N: usize>(index: usize) -> [bool; N] {
+    let mut outputs = [false; N];
+    outputs[index] = true;
+    outputs
+}
+
+/// Outputs of an `N` output chain packed as in `write_bytes`
+fn unpack<const N: usize>(bytes: &[u8]) -> [bool; N] {
+    core::array::from_fn(|i| bytes[i / 8] & (1 << (i % 8)) != 0)
+}
+
+#[test]
+fn set_single_pin() {
+    let expectations = Expectations::default()
+        .write(&only::<8>(0))
+        .write(&[false; 8]);
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    {
+        let mut pins = shift_register.decompose();
+        pins[0].set_high().unwrap();
+        assert!(pins[0].is_set_high().unwrap());
+        assert!(pins[1].is_set_low().unwrap());
+        pins[0].set_low().unwrap();
+        assert!(pins[0].is_set_low().unwrap());
+    }
+
+    done(shift_register.release());
+}
+
+#[test]
+fn toggle_pin() {
+    let expectations = Expectations::default()
+        .write(&only::<8>(5))
+        .write(&[false; 8]);
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    {
+        let mut pins = shift_register.decompose();
+        pins[5].toggle().unwrap();
+        pins[5].toggle().unwrap();
+    }
+
+    done(shift_register.release());
+}
+
+fn set_every_index<const N: usize, const B: usize>() {
+    for index in 0..N {
+        let expectations = Expectations::default()
+            .write(&only::<N>(index))
+            .write(&[false; N]);
+        let (clock, latch, data) = expectations.pins();
+        let shift_register = ShiftRegister::<_, N, B>::new(clock, latch, data);
+
+        {
+            let mut pins = shift_register.decompose();
+            pins[index].set_high().unwrap();
+            assert_eq!(shift_register.state_bytes::<16>(), {
+                let mut bytes = [0; 16];
+                bytes[index / 8] = 1 << (index % 8);
+                bytes
+            });
+            pins[index].set_low().unwrap();
+        }
+
+        done(shift_register.release());
+    }
+}
+
+#[test]
+fn set_every_index_of_one_register() {
+    set_every_index::<8, 1>();
+}
+
+#[test]
+fn set_every_index_of_two_registers() {
+    set_every_index::<16, 2>();
+}
+
+#[test]
+fn set_every_index_of_a_partial_register() {
+    set_every_index::<5, 1>();
+}
+
+#[test]
+fn write_chain_of_8() {
+    let expectations = Expectations::default().write(&unpack::<8>(&[0xA5]));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    shift_register.write_u8(0xA5).unwrap();
+    assert_eq!(shift_register.state_u8(), 0xA5);
+
+    done(shift_register.release());
+}
+
+#[test]
+fn write_chain_of_16() {
+    let expectations = Expectations::default().write(&unpack::<16>(&0x1234u16.to_le_bytes()));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 16, 2>::new(clock, latch, data);
+
+    shift_register.write_u16(0x1234).unwrap();
+    assert_eq!(shift_register.state_u16(), 0x1234);
+
+    done(shift_register.release());
+}
+
+#[test]
+fn write_chain_of_128() {
+    let value = 0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210u128;
+    let expectations = Expectations::default().write(&unpack::<128>(&value.to_le_bytes()));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 128, 16>::new(clock, latch, data);
+
+    shift_register.write_u128(value).unwrap();
+    assert_eq!(shift_register.state_u128(), value);
+
+    done(shift_register.release());
+}
+
+#[test]
+fn count_pin_operations() {
+    let (clock, latch, data) = (Cell::new(0), Cell::new(0), Cell::new(0));
+    let shift_register = ShiftRegister::<_, 16, 2>::new(
+        CountingPin(&clock),
+        CountingPin(&latch),
+        CountingPin(&data),
+    );
+
+    for (value, data_writes) in [
+        (0x0000, 1),
+        (0xFFFF, 1),
+        (0x00FF, 2),
+        (0x5555, 16),
+        (0x0F0F, 4),
+    ] {
+        for count in [&clock, &latch, &data] {
+            count.set(0);
+        }
+        shift_register.write_u16(value).unwrap();
+        assert_eq!(clock.get(), 32, "clock pin writes for {:#06x}", value);
+        assert_eq!(latch.get(), 2, "latch pin writes for {:#06x}", value);
+        assert_eq!(
+            data.get(),
+            data_writes,
+            "data pin writes for {:#06x}",
+            value
+        );
+    }
+}
+
+#[test]
+fn batch_writes_once() {
+    let mut outputs = [false; 16];
+    outputs[2] = true;
+    outputs[11] = true;
+    let expectations = Expectations::default().write(&outputs);
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 16, 2>::new(clock, latch, data);
+
+    {
+        let mut pins = shift_register.decompose();
+        shift_register
+            .batch(|| {
+                pins[2].set_high().unwrap();
+                pins[11].set_high().unwrap();
+            })
+            .unwrap();
+    }
+
+    done(shift_register.release());
+}
+
+#[test]
+fn panicking_batch_stops_deferring() {
+    let (clock, latch, data) = (Cell::new(0), Cell::new(0), Cell::new(0));
+    let shift_register =
+        ShiftRegister::<_, 8, 1>::new(CountingPin(&clock), CountingPin(&latch), CountingPin(&data));
+    let mut pins = shift_register.decompose();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        shift_register.batch(|| {
+            pins[0].set_high().unwrap();
+            panic!("batch aborted");
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(clock.get(), 0);
+
+    pins[1].set_high().unwrap();
+    assert_eq!(clock.get(), 16);
+    assert!(shift_register.is_synced());
+}
+
+#[test]
+fn skip_unchanged_state() {
+    let expectations = Expectations::default()
+        .write(&only::<8>(1))
+        .write(&only::<8>(1));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    {
+        let mut pins = shift_register.decompose();
+        pins[1].set_high().unwrap();
+        pins[1].set_high().unwrap();
+        pins[2].set_low().unwrap();
+        shift_register.write_u8(0b10).unwrap();
+        shift_register
+            .batch(|| pins[1].set_high().unwrap())
+            .unwrap();
+        shift_register.resync().unwrap();
+        shift_register.force_refresh().unwrap();
+    }
+
+    done(shift_register.release());
+}
+
+#[test]
+fn latch_pin_error() {
+    let latch = [Transaction::set(State::Low).with_error(error())];
+    let pins = (PinMock::new(&[]), PinMock::new(&latch), PinMock::new(&[]));
+    let shift_register = ShiftRegister::<_, 8, 1>::new(pins.0, pins.1, pins.2);
+
+    let result = shift_register.decompose()[0].set_high();
+    let Err(Error::TransportError(error)) = result else {
+        panic!("expected a transport error, got {:?}", result);
+    };
+    assert!(matches!(error, SRError::LatchPinError(_)));
+    assert_eq!(error.operation(), Operation::Latch);
+    assert_eq!(error.position(), None);
+    assert_eq!(error.kind(), digital::ErrorKind::Other);
+    assert_eq!(
+        error.to_string(),
+        "latch pin failed while latching: Io(NotConnected)"
+    );
+    assert!(!shift_register.is_synced());
+
+    done(shift_register.release());
+}
+
+#[test]
+fn held_outputs_stay_held_until_enabled() {
+    let expectations = Expectations::default()
+        .write(&only::<8>(0))
+        .write(&only::<8>(0));
+    let (clock, latch, data) = expectations.pins();
+    let output_enable = [
+        Transaction::set(State::High),
+        Transaction::set(State::Low).with_error(error()),
+        Transaction::set(State::Low),
+    ];
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data)
+        .with_output_enable(PinMock::new(&output_enable));
+    shift_register.hold_outputs().unwrap();
+
+    let result = shift_register.decompose()[0].set_high();
+    assert!(matches!(result, Err(Error::OutputEnablePinError(_))));
+    shift_register.force_refresh().unwrap();
+
+    let (shift_register, output_enable) = shift_register.without_output_enable();
+    output_enable.unwrap().done();
+    done(shift_register.release());
+}
+
+#[test]
+fn same_level_write_retries_enabling_held_outputs() {
+    let expectations = Expectations::default().write(&only::<8>(0));
+    let (clock, latch, data) = expectations.pins();
+    let output_enable = [
+        Transaction::set(State::High),
+        Transaction::set(State::Low).with_error(error()),
+        Transaction::set(State::Low).with_error(error()),
+        Transaction::set(State::Low),
+    ];
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data)
+        .with_output_enable(PinMock::new(&output_enable));
+    shift_register.hold_outputs().unwrap();
+
+    {
+        let mut pins = shift_register.decompose();
+        let result = pins[0].set_high();
+        assert!(matches!(result, Err(Error::OutputEnablePinError(_))));
+        let result = shift_register.batch(|| ());
+        assert!(matches!(result, Err(Error::OutputEnablePinError(_))));
+        pins[0].set_high().unwrap();
+        pins[0].set_high().unwrap();
+    }
+
+    let (shift_register, output_enable) = shift_register.without_output_enable();
+    output_enable.unwrap().done();
+    done(shift_register.release());
+}
+
+#[test]
+fn failed_hold_keeps_the_pins() {
+    let output_enable = [Transaction::set(State::High).with_error(error())];
+    let shift_register =
+        ShiftRegister::<_, 8, 1>::new(PinMock::new(&[]), PinMock::new(&[]), PinMock::new(&[]))
+            .with_output_enable(PinMock::new(&output_enable));
+
+    let result = shift_register.hold_outputs();
+    assert!(matches!(result, Err(Error::OutputEnablePinError(_))));
+
+    let (shift_register, output_enable) = shift_register.without_output_enable();
+    output_enable.unwrap().done();
+    done(shift_register.release());
+}
+
+#[test]
+fn clear_waits_for_the_reset_pulse() {
+    let (clock, latch, data) = (Cell::new(0), Cell::new(0), Cell::new(0));
+    let waited = Cell::new(0);
+    let timing = Timing {
+        data_setup_ns: 0,
+        clock_high_ns: 0,
+        clock_low_ns: 0,
+        latch_pulse_ns: 0,
+        reset_pulse_ns: 250,
+    };
+    let transport = BitBang::new(CountingPin(&clock), CountingPin(&latch), CountingPin(&data))
+        .with_delay(TotalDelay(&waited), timing);
+    let reset = [Transaction::set(State::Low), Transaction::set(State::High)];
+    let shift_register =
+        ShiftRegister::<_, 8, 1>::with_transport(transport).with_reset(PinMock::new(&reset));
+
+    shift_register.clear().unwrap();
+    assert_eq!(waited.get(), 500);
+    assert_eq!(clock.get(), 0);
+    assert_eq!(latch.get(), 2);
+
+    let (_, reset) = shift_register.without_reset();
+    reset.unwrap().done();
+}
+
+#[test]
+fn clock_pin_error() {
+    let mut expectations = Expectations::default();
+    expectations.latch.push(Transaction::set(State::Low));
+    expectations.data.push(Transaction::set(State::Low));
+    for _ in 0..3 {
+        expectations.clock.push(Transaction::set(State::High));
+        expectations.clock.push(Transaction::set(State::Low));
+    }
+    expectations
+        .clock
+        .push(Transaction::set(State::High).with_error(error()));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    let result = shift_register.decompose()[0].set_high();
+    let Err(Error::TransportError(error)) = result else {
+        panic!("expected a transport error, got {:?}", result);
+    };
+    assert!(matches!(error, SRError::ClockPinError { position: 3, .. }));
+    assert_eq!(error.operation(), Operation::Shift);
+    assert_eq!(error.position(), Some(3));
+    assert_eq!(
+        error.to_string(),
+        "clock pin failed while shifting bit 3: Io(NotConnected)"
+    );
+    assert!(!shift_register.is_synced());
+
+    done(shift_register.release());
+}
+
+#[test]
+fn clock_pin_error_on_falling_edge() {
+    let mut expectations = Expectations::default();
+    expectations.latch.push(Transaction::set(State::Low));
+    expectations.data.push(Transaction::set(State::Low));
+    for _ in 0..2 {
+        expectations.clock.push(Transaction::set(State::High));
+        expectations.clock.push(Transaction::set(State::Low));
+    }
+    expectations.clock.push(Transaction::set(State::High));
+    expectations
+        .clock
+        .push(Transaction::set(State::Low).with_error(error()));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    let result = shift_register.decompose()[0].set_high();
+    let Err(Error::TransportError(error)) = result else {
+        panic!("expected a transport error, got {:?}", result);
+    };
+    assert!(matches!(error, SRError::ClockPinError { position: 3, .. }));
+    assert_eq!(error.position(), Some(3));
+    assert!(!shift_register.is_synced());
+
+    done(shift_register.release());
+}
+
+#[test]
+fn data_pin_error() {
+    let mut expectations = Expectations::default();
+    expectations.latch.push(Transaction::set(State::Low));
+    expectations
+        .data
+        .push(Transaction::set(State::Low).with_error(error()));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    let result = shift_register.decompose()[0].set_high();
+    let Err(Error::TransportError(error)) = result else {
+        panic!("expected a transport error, got {:?}", result);
+    };
+    assert!(matches!(error, SRError::DataPinError { position: 0, .. }));
+    assert_eq!(error.operation(), Operation::Shift);
+    assert_eq!(error.position(), Some(0));
+    assert!(!shift_register.is_synced());
+
+    done(shift_register.release());
+}
+
+#[test]
+fn resync_after_error() {
+    let mut expectations = Expectations::default();
+    expectations
+        .latch
+        .push(Transaction::set(State::Low).with_error(error()));
+    let expectations = expectations.write(&only::<8>(4));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock, latch, data);
+
+    assert!(!shift_register.is_synced());
+    assert!(shift_register.decompose()[4].set_high().is_err());
+    assert!(!shift_register.is_synced());
+    shift_register.resync().unwrap();
+    assert!(shift_register.is_synced());
+
+    done(shift_register.release());
+}
+
+#[test]
+fn release_returns_the_pins() {
+    let mut expectations = Expectations::default().write(&only::<8>(7));
+    expectations.clock.push(Transaction::set(State::High));
+    expectations.latch.push(Transaction::set(State::Low));
+    expectations.data.push(Transaction::set(State::High));
+    let (clock, latch, data) = expectations.pins();
+    let shift_register = ShiftRegister::<_, 8, 1>::new(clock.clone(), latch.clone(), data.clone());
+
+    shift_register.decompose()[7].set_high().unwrap();
+
+    // Clones of a mock share its expectations, so these are only consumed if `release` hands
+    // back the pins the shift register was created with
+    let (mut released_clock, mut released_latch, mut released_data) = shift_register.release();
+    released_clock.set_high().unwrap();
+    released_latch.set_low().unwrap();
+    released_data.set_high().unwrap();
+    done((clock, latch, data));
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "std")]
+
+use shift_register_driver::sipo::{BitBang, NoPin, ShiftRegister, Timing};
+use shift_register_driver::vcd::Recorder;
+
+#[test]
+fn golden_dump_of_a_write() {
+    let recorder = Recorder::with_virtual_time();
+    let timing = Timing {
+        data_setup_ns: 10,
+        clock_high_ns: 20,
+        clock_low_ns: 20,
+        latch_pulse_ns: 30,
+        reset_pulse_ns: 0,
+    };
+    let transport = BitBang::new(
+        recorder.pin("clock", NoPin),
+        recorder.pin("latch", NoPin),
+        recorder.pin("data", NoPin),
+    )
+    .with_delay(recorder.delay(), timing);
+    let shift_register = ShiftRegister::<_, 2, 1>::with_transport(transport);
+
+    shift_register.write_u8(0b01).unwrap();
+
+    assert_eq!(
+        recorder.to_vcd(),
+        "\
+$timescale 1ns $end
+$scope module shift_register $end
+$var wire 1 ! clock $end
+$var wire 1 \" latch $end
+$var wire 1 # data $end
+$upscope $end
+$enddefinitions $end
+$dumpvars
+x!
+x\"
+x#
+$end
+#0
+0\"
+#31
+0#
+#42
+1!
+#63
+0!
+#84
+1#
+#95
+1!
+#116
+0!
+#137
+1\"
+"
+    );
+}